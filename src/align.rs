@@ -0,0 +1,316 @@
+use bio::alignment::pairwise::*;
+use bio::alignment::AlignmentOperation;
+
+use crate::con::WriteSequence;
+use crate::utils::alignment::{Alignment, Sequence};
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+use crate::utils::revcomp::reverse_complement;
+
+// a quick aligner, so copies pulled from the genome can go straight
+// into the rest of reputils without MAFFT. each sequence in turn is
+// aligned to the consensus of those already aligned, and any bases
+// it has which the consensus does not are added as new columns.
+
+/// Columns with bases in fewer than this proportion of the aligned
+/// sequences are left out of the consensus sequences are aligned to,
+/// so one-off insertions and flanks don't attract later sequences.
+const REFERENCE_OCCUPANCY: f64 = 0.5;
+
+// the majority base of each well occupied column, with the indices
+// of those columns in the alignment.
+fn reference(matrix: &[Sequence]) -> (Vec<u8>, Vec<usize>) {
+    let n = matrix.len() as f64;
+    let mut reference = Vec::new();
+    let mut columns = Vec::new();
+    for col in 0..matrix[0].len() {
+        let mut counts = [0usize; 5];
+        for row in matrix {
+            match row.sequence[col].to_ascii_uppercase() {
+                b'A' => counts[0] += 1,
+                b'C' => counts[1] += 1,
+                b'G' => counts[2] += 1,
+                b'T' => counts[3] += 1,
+                b'-' => (),
+                _ => counts[4] += 1,
+            }
+        }
+        let occupied: usize = counts.iter().sum();
+        if occupied == 0 || (occupied as f64) < REFERENCE_OCCUPANCY * n {
+            continue;
+        }
+        let (best, count) = counts[..4]
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, c)| **c)
+            .unwrap();
+        reference.push(if *count == 0 { b'N' } else { b"ACGT"[best] });
+        columns.push(col);
+    }
+    (reference, columns)
+}
+
+/// Add `sequence` (ungapped) to the alignment, given its alignment to
+/// the reference made from `columns`. Bases the reference doesn't have
+/// go into the columns already between the reference columns either
+/// side (towards the aligned part, at the ends), and only add new
+/// columns if there aren't enough.
+fn add_to_alignment(
+    matrix: &mut Vec<Sequence>,
+    mut sequence: Sequence,
+    aligned: &bio::alignment::Alignment,
+    columns: &[usize],
+) {
+    let length = matrix[0].len();
+    let bases = std::mem::take(&mut sequence.sequence);
+    let mut row = vec![b'-'; length];
+    // bases before each reference column (the last being after the end),
+    // and whether they come before any aligned base.
+    let mut runs: Vec<(Vec<u8>, bool)> = vec![(Vec::new(), false); columns.len() + 1];
+
+    let (mut x, mut y) = (aligned.xstart, aligned.ystart);
+    let mut leading = true;
+    for op in &aligned.operations {
+        match op {
+            AlignmentOperation::Match | AlignmentOperation::Subst => {
+                row[columns[y]] = bases[x];
+                leading = false;
+                x += 1;
+                y += 1;
+            }
+            AlignmentOperation::Del => y += 1,
+            AlignmentOperation::Ins => {
+                runs[y].0.push(bases[x]);
+                runs[y].1 = leading;
+                x += 1;
+            }
+            _ => (),
+        }
+    }
+    // semiglobal, so this is only in case of no alignment at all.
+    runs[y].0.extend_from_slice(&bases[x..]);
+
+    // bases to insert as new columns before each column.
+    let mut inserts: Vec<Vec<u8>> = vec![Vec::new(); length + 1];
+    for (y, (run, leading)) in runs.into_iter().enumerate() {
+        let lo = if y == 0 { 0 } else { columns[y - 1] + 1 };
+        let hi = columns.get(y).copied().unwrap_or(length);
+        let free = hi - lo;
+        if run.len() <= free {
+            let start = if leading { hi - run.len() } else { lo };
+            row[start..start + run.len()].copy_from_slice(&run);
+        } else if leading {
+            let extra = run.len() - free;
+            inserts[lo] = run[..extra].to_vec();
+            row[lo..hi].copy_from_slice(&run[extra..]);
+        } else {
+            row[lo..hi].copy_from_slice(&run[..free]);
+            inserts[hi] = run[free..].to_vec();
+        }
+    }
+
+    for old in matrix.iter_mut() {
+        old.sequence = splice_row(&old.sequence, &inserts, |_| b'-');
+    }
+    sequence.sequence = splice_row(&row, &inserts, |b| b);
+    matrix.push(sequence);
+}
+
+// a row with the inserted columns added, filling them with `fill`.
+fn splice_row(row: &[u8], inserts: &[Vec<u8>], fill: impl Fn(u8) -> u8) -> Vec<u8> {
+    let extra: usize = inserts.iter().map(|i| i.len()).sum();
+    let mut new = Vec::with_capacity(row.len() + extra);
+    for (col, insert) in inserts.iter().enumerate() {
+        new.extend(insert.iter().map(|&b| fill(b)));
+        if col < row.len() {
+            new.push(row[col]);
+        }
+    }
+    new
+}
+
+/// Align unaligned sequences, starting from the longest, and adding
+/// the rest longest first. They come out in the order they went in.
+/// With `adjust_direction`, sequences which align better reverse
+/// complemented are, and get the `_R_` prefix MAFFT uses. Any gaps in
+/// the input are removed first.
+pub fn progressive_align(sequences: Vec<Sequence>, adjust_direction: bool) -> Alignment {
+    let mut sequences: Vec<(usize, Sequence)> = sequences
+        .into_iter()
+        .map(|mut s| {
+            s.sequence.retain(|&b| b != b'-');
+            s
+        })
+        .filter(|s| {
+            if s.is_empty() {
+                eprintln!("[-]\t{} has no sequence. Skipping.", s.name);
+            }
+            !s.is_empty()
+        })
+        .enumerate()
+        .collect();
+    // stable, so equal lengths stay in file order.
+    sequences.sort_by_key(|(_, s)| std::cmp::Reverse(s.len()));
+
+    let mut alignment = Alignment::new();
+    let mut order = Vec::with_capacity(sequences.len());
+    let mut sequences = sequences.into_iter();
+    match sequences.next() {
+        Some((index, seed)) => {
+            order.push(index);
+            alignment.add_sequence(seed);
+        }
+        None => return alignment,
+    }
+
+    let score = |a: u8, b: u8| {
+        if a.eq_ignore_ascii_case(&b) {
+            1i32
+        } else {
+            -1i32
+        }
+    };
+    let longest = alignment.matrix[0].len();
+    // gap open score: -5, gap extension score: -1
+    let mut aligner = Aligner::with_capacity(longest, longest, -5, -1, &score);
+
+    let mut reversed = 0;
+    for (index, mut sequence) in sequences {
+        let (reference, columns) = reference(&alignment.matrix);
+        // the sequence is aligned end to end, within the reference.
+        let mut aligned = aligner.semiglobal(&sequence.sequence, &reference);
+
+        if adjust_direction {
+            let reverse = reverse_complement(&sequence.sequence);
+            let aligned_reverse = aligner.semiglobal(&reverse, &reference);
+            if aligned_reverse.score > aligned.score {
+                aligned = aligned_reverse;
+                sequence.sequence = reverse;
+                sequence.name = format!("_R_{}", sequence.name);
+                if let Some(locus) = sequence.locus.as_mut() {
+                    locus.strand = locus.strand.flip();
+                }
+                reversed += 1;
+            }
+        }
+
+        add_to_alignment(&mut alignment.matrix, sequence, &aligned, &columns);
+        order.push(index);
+    }
+
+    let mut ordered: Vec<(usize, Sequence)> = order.into_iter().zip(alignment.matrix).collect();
+    ordered.sort_by_key(|(index, _)| *index);
+    alignment.matrix = ordered.into_iter().map(|(_, s)| s).collect();
+
+    if reversed > 0 {
+        eprintln!("[+]\t{} sequences reverse complemented.", reversed);
+    }
+    alignment
+}
+
+pub fn align(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let adjust_direction = matches.is_present("adjust_direction");
+
+    let sequences = read_alignment_as(fasta, format_from_matches(matches)).matrix;
+    let n = sequences.len();
+    let alignment = progressive_align(sequences, adjust_direction);
+
+    for record in alignment.matrix.iter() {
+        println!(
+            ">{}\n{}",
+            record.name,
+            WriteSequence(record.sequence.clone())
+        );
+    }
+
+    eprintln!(
+        "[+]\t{} of {} sequences aligned, over {} columns.",
+        alignment.matrix.len(),
+        n,
+        alignment.matrix.first().map_or(0, |s| s.len())
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: &str = "ACGTTGCAAGCTAGGCTTACCGATGGACTC";
+
+    fn sequence(name: &str, bases: &str) -> Sequence {
+        Sequence {
+            name: name.to_string(),
+            sequence: bases.as_bytes().to_vec(),
+            locus: None,
+        }
+    }
+
+    fn rows(alignment: &Alignment) -> Vec<(&str, &str)> {
+        alignment
+            .matrix
+            .iter()
+            .map(|s| (s.name.as_str(), std::str::from_utf8(&s.sequence).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn inserts_share_columns_and_keep_the_input_order() {
+        let with_ttt = format!("{}TTT{}", &SEED[..10], &SEED[10..25]);
+        let with_gg = format!("{}GG{}", &SEED[..10], &SEED[10..24]);
+        let sequences = vec![
+            sequence("gg", &with_gg),
+            sequence("seed", SEED),
+            sequence("ttt", &with_ttt),
+        ];
+        let alignment = progressive_align(sequences, false);
+        // TTT gets new columns, which GG then reuses.
+        assert_eq!(
+            rows(&alignment),
+            vec![
+                (
+                    "gg",
+                    format!("{}-GG{}------", &SEED[..10], &SEED[10..24]).as_str()
+                ),
+                (
+                    "seed",
+                    format!("{}---{}", &SEED[..10], &SEED[10..]).as_str()
+                ),
+                (
+                    "ttt",
+                    format!("{}TTT{}-----", &SEED[..10], &SEED[10..25]).as_str()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn leading_bases_go_before_the_first_column() {
+        let leading = format!("CCCC{}", &SEED[..20]);
+        let alignment = progressive_align(
+            vec![sequence("seed", SEED), sequence("leading", &leading)],
+            false,
+        );
+        assert_eq!(
+            rows(&alignment),
+            vec![
+                ("seed", format!("----{}", SEED).as_str()),
+                ("leading", format!("{}----------", leading).as_str()),
+            ]
+        );
+    }
+
+    #[test]
+    fn adjust_direction_reverse_complements() {
+        let reverse = reverse_complement(&SEED.as_bytes()[5..25]);
+        let reverse = std::str::from_utf8(&reverse).unwrap();
+        let sequences = vec![sequence("seed", SEED), sequence("copy", reverse)];
+
+        let alignment = progressive_align(sequences.clone(), true);
+        assert_eq!(alignment.matrix[1].name, "_R_copy");
+        let expected = format!("-----{}-----", &SEED[5..25]);
+        assert_eq!(alignment.matrix[1].sequence, expected.as_bytes());
+
+        let alignment = progressive_align(sequences, false);
+        assert_eq!(alignment.matrix[1].name, "copy");
+    }
+}
@@ -0,0 +1,346 @@
+// run the html pipeline (trim, consensus, TIR, TSD, diversity)
+// over a directory of alignments, one family per file.
+// each family gets its own outputs, plus a row in a summary table.
+
+use clap::value_t;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::con::WriteSequence;
+use crate::html::{Pipeline, Stages};
+use crate::tsd::top_tsd;
+use crate::utils::formats::try_read_alignment_as;
+
+// file extensions treated as alignments.
+const EXTENSIONS: [&str; 11] = [
+    "fa", "fasta", "fas", "fna", "afa", "sto", "stk", "aln", "phy", "a2m", "a3m",
+];
+
+/// One row of the summary table.
+#[derive(Debug, Clone)]
+pub struct FamilySummary {
+    pub family: String,
+    pub sequences: usize,
+    pub alignment_length: usize,
+    pub trimmed_length: usize,
+    pub consensus_length: usize,
+    pub tir_length: usize,
+    pub tir_identity: f64,
+    pub tir_pass: bool,
+    // the TSD found in the most sequences, and how many.
+    pub top_tsd: String,
+    pub top_tsd_count: usize,
+    pub mean_diversity: f32,
+    /// over the windows with enough sites compared.
+    pub mean_diversity_per_site: Option<f32>,
+}
+
+impl FamilySummary {
+    pub const HEADER: &'static str = "Family\tSequences\tAlignment length\tTrimmed length\tConsensus length\tTIR length\tTIR identity\tTIR\tTop TSD\tTSD count\tMean diversity\tMean diversity per site";
+}
+
+impl std::fmt::Display for FamilySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{:.3}\t{}\t{}\t{}\t{:.3}\t{}",
+            self.family,
+            self.sequences,
+            self.alignment_length,
+            self.trimmed_length,
+            self.consensus_length,
+            self.tir_length,
+            self.tir_identity,
+            if self.tir_pass { "PASS" } else { "FAIL" },
+            self.top_tsd,
+            self.top_tsd_count,
+            self.mean_diversity,
+            self.mean_diversity_per_site
+                .map_or("-".to_string(), |p| format!("{:.4}", p))
+        )
+    }
+}
+
+pub fn batch(matches: &clap::ArgMatches) {
+    let dir = matches.value_of("dir").unwrap();
+    let out = matches.value_of("out").unwrap();
+    let threads = value_t!(matches.value_of("threads"), usize).unwrap_or_else(|e| e.exit());
+
+    let pipeline = Pipeline::from_matches(matches);
+
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("[-]\tCould not read input directory.")
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| EXTENSIONS.contains(&e))
+        })
+        .collect();
+    files.sort();
+
+    if files.is_empty() {
+        eprintln!("[-]\tNo alignments found in {}. Exiting.", dir);
+        std::process::exit(1);
+    }
+
+    // outputs are named by family, so a.fa and a.sto would overwrite each other.
+    let duplicates = duplicate_families(&files);
+    if !duplicates.is_empty() {
+        eprintln!(
+            "[-]\tMore than one alignment for families {} in {}. Exiting.",
+            duplicates.join(", "),
+            dir
+        );
+        std::process::exit(1);
+    }
+
+    fs::create_dir_all(out).expect("[-]\tCould not create output directory.");
+
+    // zero threads means use all of them.
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        t => t,
+    }
+    .min(files.len());
+    eprintln!(
+        "[+]\tProcessing {} alignments on {} threads.",
+        files.len(),
+        threads
+    );
+
+    let summaries = run_batch(&files, Path::new(out), &pipeline, threads);
+
+    let summary_path = Path::new(out).join("summary.tsv");
+    let mut summary =
+        BufWriter::new(File::create(&summary_path).expect("[-]\tCould not create summary file."));
+    writeln!(summary, "{}", FamilySummary::HEADER).unwrap();
+    for row in &summaries {
+        writeln!(summary, "{}", row).unwrap();
+    }
+    eprintln!(
+        "[+]\t{} of {} alignments processed. Summary written to {}",
+        summaries.len(),
+        files.len(),
+        summary_path.display()
+    );
+}
+
+/// Run the pipeline over each file, sharing the files between `threads`
+/// workers. Families which fail are reported and left out of the summary.
+pub fn run_batch(
+    files: &[PathBuf],
+    out: &Path,
+    pipeline: &Pipeline,
+    threads: usize,
+) -> Vec<FamilySummary> {
+    // the next file to be picked up by a worker.
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| {
+        for _ in 0..threads {
+            let tx = tx.clone();
+            let next = &next;
+            s.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let path = match files.get(index) {
+                    Some(p) => p,
+                    None => break,
+                };
+                // a bad alignment should not take the whole batch down.
+                match panic::catch_unwind(AssertUnwindSafe(|| run_family(path, out, pipeline))) {
+                    Ok(Ok(summary)) => tx.send(summary).unwrap(),
+                    Ok(Err(e)) => eprintln!("[-]\tSkipping {}: {}", path.display(), e),
+                    Err(_) => eprintln!("[-]\tFailed on {}, skipping.", path.display()),
+                }
+            });
+        }
+    });
+    drop(tx);
+
+    let mut summaries: Vec<FamilySummary> = rx.iter().collect();
+    summaries.sort_by(|a, b| a.family.cmp(&b.family));
+    summaries
+}
+
+// the family an alignment is for, named after the file.
+fn family_name(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("family")
+        .to_string()
+}
+
+/// Families with more than one alignment among `files`, sorted.
+pub fn duplicate_families(files: &[PathBuf]) -> Vec<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for path in files {
+        *counts.entry(family_name(path)).or_insert(0) += 1;
+    }
+    let mut duplicates: Vec<String> = counts
+        .into_iter()
+        .filter(|(_, n)| *n > 1)
+        .map(|(family, _)| family)
+        .collect();
+    duplicates.sort();
+    duplicates
+}
+
+/// Run the pipeline on a single family, writing the consensus, trimmed
+/// alignment, TSD table and diversity windows into `out`. Errors reading
+/// the alignment or writing the outputs are returned.
+pub fn run_family(path: &Path, out: &Path, pipeline: &Pipeline) -> Result<FamilySummary, String> {
+    let family = family_name(path);
+
+    let alignment = try_read_alignment_as(path, pipeline.input_format)?;
+    if alignment.matrix.is_empty() {
+        return Err("No sequences in the alignment.".to_string());
+    }
+    let read_number = alignment.matrix.len();
+    let alignment_length = alignment.matrix[0].len();
+
+    let stages = pipeline.run(&alignment);
+    let (top_tsd, top_tsd_count) = top_tsd(&stages.tsds).unwrap_or_else(|| ("-".to_string(), 0));
+
+    // diversity
+    let div = &stages.div;
+    let mean_diversity = if div.is_empty() {
+        0.0
+    } else {
+        div.iter().map(|w| w.pi).sum::<f32>() / div.len() as f32
+    };
+    let per_site: Vec<f32> = div.iter().filter_map(|w| w.pi_per_site).collect();
+    let mean_diversity_per_site =
+        (!per_site.is_empty()).then(|| per_site.iter().sum::<f32>() / per_site.len() as f32);
+
+    write_family(&family, out, &stages)
+        .map_err(|e| format!("Could not write the outputs: {}.", e))?;
+
+    eprintln!("[+]\tFinished {}.", family);
+
+    Ok(FamilySummary {
+        family,
+        sequences: read_number,
+        alignment_length,
+        trimmed_length: stages.trimmed.matrix.first().map_or(0, |s| s.len()),
+        consensus_length: stages.consensus.len(),
+        tir_length: stages.tir.length,
+        tir_identity: stages.tir.identity,
+        tir_pass: stages.tir.pass,
+        top_tsd,
+        top_tsd_count,
+        mean_diversity,
+        mean_diversity_per_site,
+    })
+}
+
+// per family outputs
+fn write_family(family: &str, out: &Path, stages: &Stages) -> io::Result<()> {
+    let create =
+        |suffix: &str| File::create(out.join(format!("{}.{}", family, suffix))).map(BufWriter::new);
+
+    let mut con_file = create("con.fa")?;
+    writeln!(
+        con_file,
+        ">{}\n{}",
+        family,
+        WriteSequence(stages.consensus.clone())
+    )?;
+
+    let mut ttc_file = create("ttc.fa")?;
+    for seq in &stages.trimmed.matrix {
+        writeln!(
+            ttc_file,
+            ">{}\n{}",
+            seq.name,
+            WriteSequence(seq.sequence.clone())
+        )?;
+    }
+
+    let mut tsd_file = create("tsd.tsv")?;
+    writeln!(tsd_file, "ID\tTSD's")?;
+    let mut ids: Vec<&String> = stages.tsds.keys().collect();
+    ids.sort();
+    for id in ids {
+        writeln!(tsd_file, "{}\t{}", id, stages.tsds[id].join("\t"))?;
+    }
+
+    let mut div_file = create("div.tsv")?;
+    writeln!(div_file, "Start\tEnd\tPi\tPi per site\tSites")?;
+    for window in &stages.div {
+        writeln!(div_file, "{}", window)?;
+    }
+    // flush here, so a failed write is not lost when the writers drop.
+    con_file.flush()?;
+    ttc_file.flush()?;
+    tsd_file.flush()?;
+    div_file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::con::ConsensusParams;
+
+    // the defaults of `reputils batch`.
+    fn pipeline() -> Pipeline {
+        Pipeline {
+            input_format: None,
+            trim_extend: 30,
+            trim_next_hit: 1,
+            trim_miss: 0.1,
+            trim_iden: 0.85,
+            consensus: ConsensusParams::default(),
+            tir_window: 100,
+            tir_min_len: 10,
+            tir_min_iden: 0.8,
+            tsd_len: 30,
+            tsd_min_window: 2,
+            tsd_max_window: 12,
+            div_window_size: 10,
+            div_window_step: 3,
+            div_min_sites: 5.0,
+        }
+    }
+
+    #[test]
+    fn finds_duplicate_families() {
+        let files: Vec<PathBuf> = ["x/a.fa", "x/a.sto", "x/b.fa", "x/c.phy", "x/c.aln"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(duplicate_families(&files), vec!["a", "c"]);
+        assert!(duplicate_families(&files[2..4]).is_empty());
+    }
+
+    #[test]
+    fn bad_alignments_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("reputils_batch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let seq = "ACGTACGTTAGCATCGATCGGATCGATTACGATCGATCGACTAGCTAGCTAGGCATCGAT";
+        fs::write(dir.join("good.fa"), format!(">a\n{}\n>b\n{}\n", seq, seq)).unwrap();
+        // the header says three sequences, but there are two.
+        fs::write(
+            dir.join("bad.phy"),
+            format!("3 {}\na {}\nb {}\n", seq.len(), seq, seq),
+        )
+        .unwrap();
+
+        let files = vec![dir.join("bad.phy"), dir.join("good.fa")];
+        let summaries = run_batch(&files, &dir, &pipeline(), 2);
+        let written = dir.join("good.con.fa").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].family, "good");
+        assert_eq!(summaries[0].sequences, 2);
+        assert!(written);
+    }
+}
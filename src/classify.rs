@@ -0,0 +1,478 @@
+use clap::value_t;
+use std::path::Path;
+
+use crate::con::{trimmed_consensus_columns, ConsensusParams, WriteSequence};
+use crate::helitron::{find_helitron, Helitron, HelitronParams};
+use crate::ltr::{find_ltr, Ltr};
+use crate::tail::{find_tail, Tail, TailParams};
+use crate::tir::{find_tir, Tir};
+use crate::tsd::top_tsd;
+use crate::utils::flanks::boundary_tsds;
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+use crate::utils::seqcount::most_common;
+
+// put the structural evidence together (TIR's, LTR's, TSD's and the
+// terminal motifs of the consensus) and make a Wicker style superfamily call.
+// each superfamily has a few rules, and the one with the most support wins.
+// it's a first guess for curation, not a replacement for it.
+
+// how far (bp) a TIR or LTR may sit from the end of the consensus
+// and still count as terminal. the trimmed consensus keeps a little flank.
+const TERMINAL_SLOP: usize = 30;
+
+/// The evidence a classification is made from.
+#[derive(Debug, Clone)]
+pub struct Evidence {
+    pub length: usize,
+    pub tir: Tir,
+    pub ltr: Ltr,
+    /// the TSD found in the most sequences, and how many.
+    pub tsd: Option<(String, usize)>,
+    /// the most common TSD length, and how many sequences have it.
+    pub tsd_length: Option<(usize, usize)>,
+    pub sequences: usize,
+    /// first and last ten bases of the element.
+    pub five_prime: String,
+    pub three_prime: String,
+    /// a poly-A or other simple sequence tail ending near the 3' end.
+    pub tail: Option<Tail>,
+    pub helitron: Option<Helitron>,
+}
+
+impl Evidence {
+    pub fn new(consensus: &[u8], tir: Tir, ltr: Ltr, sequences: usize) -> Self {
+        let upper = consensus.to_ascii_uppercase();
+        let mut evidence = Evidence {
+            length: upper.len(),
+            tir,
+            ltr,
+            tsd: None,
+            tsd_length: None,
+            sequences,
+            five_prime: String::new(),
+            three_prime: String::new(),
+            tail: None,
+            helitron: None,
+        };
+
+        // terminal repeats mark the ends of the element better than
+        // the ends of the consensus, which may carry some flank.
+        let (start, end) = evidence.element().unwrap_or((0, upper.len()));
+        let element = &upper[start..end];
+        let ends = 10.min(element.len());
+        evidence.five_prime = String::from_utf8_lossy(&element[..ends]).to_string();
+        evidence.three_prime =
+            String::from_utf8_lossy(&element[element.len() - ends..]).to_string();
+        let params = TailParams::default();
+        evidence.tail = find_tail(
+            &upper,
+            upper.len().saturating_sub(TERMINAL_SLOP + params.search)..upper.len(),
+            &params,
+        )
+        .filter(|t| t.end + TERMINAL_SLOP >= upper.len());
+        evidence
+    }
+
+    /// The ends of the element in the consensus, if terminal
+    /// repeats mark them.
+    pub fn element(&self) -> Option<(usize, usize)> {
+        if self.terminal_tir() {
+            Some((self.tir.left_start, self.tir.right_end))
+        } else if self.terminal_ltr() {
+            Some((self.ltr.five_start, self.ltr.three_end))
+        } else {
+            None
+        }
+    }
+
+    // a passing TIR which starts and ends at the termini.
+    fn terminal_tir(&self) -> bool {
+        self.tir.pass
+            && self.tir.left_start <= TERMINAL_SLOP
+            && self.length - self.tir.right_end <= TERMINAL_SLOP
+    }
+
+    fn terminal_ltr(&self) -> bool {
+        self.ltr.pass
+            && self.ltr.five_start <= TERMINAL_SLOP
+            && self.length - self.ltr.three_end <= TERMINAL_SLOP
+    }
+
+    // shared by enough of the copies to be believable.
+    fn supported(&self, count: usize) -> bool {
+        count >= 2 && count * 5 >= self.sequences
+    }
+
+    fn supported_tsd(&self) -> Option<&str> {
+        match &self.tsd {
+            Some((tsd, count)) if self.supported(*count) => Some(tsd),
+            _ => None,
+        }
+    }
+
+    fn supported_tsd_length(&self) -> usize {
+        match self.tsd_length {
+            Some((length, count)) if self.supported(count) => length,
+            _ => 0,
+        }
+    }
+}
+
+/// A superfamily call, with the rules which support it.
+#[derive(Debug, Clone)]
+pub struct Classification {
+    pub class: String,
+    pub family: String,
+    /// proportion of the superfamily's rules which fired.
+    pub confidence: f64,
+    pub rules: Vec<String>,
+}
+
+impl Classification {
+    /// RepeatMasker style Class/Family label.
+    pub fn label(&self) -> String {
+        if self.family.is_empty() {
+            self.class.clone()
+        } else {
+            format!("{}/{}", self.class, self.family)
+        }
+    }
+
+    fn unknown() -> Self {
+        Classification {
+            class: "Unknown".to_string(),
+            family: String::new(),
+            confidence: 0.0,
+            rules: Vec::new(),
+        }
+    }
+}
+
+// one superfamily: its label, and (points, rule) for each rule which can fire.
+struct Candidate {
+    class: &'static str,
+    family: &'static str,
+    max: usize,
+    fired: Vec<(usize, String)>,
+}
+
+impl Candidate {
+    fn new(class: &'static str, family: &'static str, max: usize) -> Self {
+        Candidate {
+            class,
+            family,
+            max,
+            fired: Vec::new(),
+        }
+    }
+
+    fn rule(&mut self, fired: bool, points: usize, rule: String) {
+        if fired {
+            self.fired.push((points, rule));
+        }
+    }
+
+    fn points(&self) -> usize {
+        self.fired.iter().map(|(p, _)| p).sum()
+    }
+}
+
+/// Make the superfamily call. At least two points of support are needed,
+/// otherwise the element is Unknown. Ties go to the superfamily listed first.
+pub fn classify_evidence(evidence: &Evidence) -> Classification {
+    let tir = evidence.terminal_tir();
+    let ltr = evidence.terminal_ltr();
+    let tsd = evidence.supported_tsd();
+    let tsd_len = evidence.supported_tsd_length();
+    let five = evidence.five_prime.as_str();
+    let three = evidence.three_prime.as_str();
+
+    let tir_rule = format!(
+        "terminal inverted repeat ({} bp, {:.2} identity)",
+        evidence.tir.length, evidence.tir.identity
+    );
+    let tsd_rule = |t: &str| format!("{} bp TSD ({})", t.len(), t);
+    let tsd_len_rule = format!("{} bp TSD's", tsd_len);
+
+    let mut candidates = Vec::new();
+
+    let mut c = Candidate::new("LTR", "Unknown", 4);
+    c.rule(
+        ltr,
+        2,
+        format!(
+            "long terminal repeats ({} bp, {:.2} identity)",
+            evidence.ltr.length, evidence.ltr.identity
+        ),
+    );
+    c.rule(
+        ltr && evidence.ltr.tg_ca(),
+        1,
+        "TG...CA LTR termini".to_string(),
+    );
+    c.rule((4..=6).contains(&tsd_len), 1, tsd_len_rule.clone());
+    candidates.push(c);
+
+    let mut c = Candidate::new("DNA", "TcMar", 3);
+    c.rule(tir, 1, tir_rule.clone());
+    c.rule(tsd == Some("TA"), 2, tsd_rule("TA"));
+    candidates.push(c);
+
+    let mut c = Candidate::new("DNA", "hAT", 4);
+    c.rule(tir, 1, tir_rule.clone());
+    c.rule(tsd_len == 8, 2, tsd_len_rule.clone());
+    c.rule(
+        yag(five) && yag(&reverse_complement(three)),
+        1,
+        "YAG termini".to_string(),
+    );
+    candidates.push(c);
+
+    let mut c = Candidate::new("DNA", "PiggyBac", 4);
+    c.rule(tir, 1, tir_rule.clone());
+    c.rule(tsd == Some("TTAA"), 2, tsd_rule("TTAA"));
+    c.rule(
+        five.starts_with("CC") && three.ends_with("GG"),
+        1,
+        "CC...GG termini".to_string(),
+    );
+    candidates.push(c);
+
+    let mut c = Candidate::new("DNA", "CMC-EnSpm", 4);
+    c.rule(
+        five.starts_with("CACTA") && three.ends_with("TAGTG"),
+        2,
+        "CACTA...TAGTG termini".to_string(),
+    );
+    c.rule(tir, 1, tir_rule);
+    c.rule((2..=3).contains(&tsd_len), 1, tsd_len_rule.clone());
+    candidates.push(c);
+
+    let mut c = Candidate::new("RC", "Helitron", 4);
+    if let Some(helitron) = &evidence.helitron {
+        let ctrr = !tir && !ltr && helitron.three_ctrr;
+        c.rule(
+            ctrr && helitron.five_tc,
+            1,
+            format!("TC...{} termini", helitron.three_terminus),
+        );
+        if let Some(hairpin) = &helitron.hairpin {
+            c.rule(
+                ctrr && !helitron.has_tsd(),
+                2,
+                format!(
+                    "{} bp stem hairpin {} bp from the 3' end",
+                    hairpin.stem, hairpin.distance
+                ),
+            );
+        }
+        c.rule(
+            ctrr && helitron.at_sites * 2 >= helitron.sequences,
+            1,
+            format!(
+                "A/T insertion sites ({}/{})",
+                helitron.at_sites, helitron.sequences
+            ),
+        );
+    }
+    candidates.push(c);
+
+    let mut c = Candidate::new("LINE", "Unknown", 4);
+    if let Some(tail) = &evidence.tail {
+        c.rule(
+            true,
+            2,
+            format!("{} bp ({})n 3' tail", tail.len(), tail.motif),
+        );
+    }
+    c.rule(!tir && !ltr, 1, "no terminal repeats".to_string());
+    c.rule(tsd_len >= 7, 1, tsd_len_rule.clone());
+    candidates.push(c);
+
+    // max_by_key returns the last maximum, so reverse to favour the first.
+    let best = candidates
+        .into_iter()
+        .rev()
+        .max_by_key(|c| c.points())
+        .unwrap();
+
+    if best.points() < 2 {
+        return Classification::unknown();
+    }
+    Classification {
+        class: best.class.to_string(),
+        family: best.family.to_string(),
+        confidence: best.points() as f64 / best.max as f64,
+        rules: best.fired.into_iter().map(|(_, r)| r).collect(),
+    }
+}
+
+// C or T, then AG.
+fn yag(seq: &str) -> bool {
+    let seq = seq.as_bytes();
+    seq.len() >= 3 && matches!(seq[0], b'C' | b'T') && &seq[1..3] == b"AG"
+}
+
+fn reverse_complement(seq: &str) -> String {
+    String::from_utf8(crate::utils::revcomp::reverse_complement(seq.as_bytes())).unwrap()
+}
+
+pub fn classify(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let tir_window = value_t!(matches.value_of("tir_window"), usize).unwrap_or_else(|e| e.exit());
+    let tir_min_len = value_t!(matches.value_of("tir_min_len"), usize).unwrap_or_else(|e| e.exit());
+    let tir_min_iden = value_t!(matches.value_of("tir_min_iden"), f64).unwrap_or_else(|e| e.exit());
+    let ltr_window = value_t!(matches.value_of("ltr_window"), usize).unwrap_or_else(|e| e.exit());
+    let ltr_min_len = value_t!(matches.value_of("ltr_min_len"), usize).unwrap_or_else(|e| e.exit());
+    let ltr_min_iden = value_t!(matches.value_of("ltr_min_iden"), f64).unwrap_or_else(|e| e.exit());
+    let tsd_len = value_t!(matches.value_of("tsd_len"), usize).unwrap_or_else(|e| e.exit());
+    let tsd_min_window =
+        value_t!(matches.value_of("tsd_min_window"), usize).unwrap_or_else(|e| e.exit());
+    let tsd_max_window =
+        value_t!(matches.value_of("tsd_max_window"), usize).unwrap_or_else(|e| e.exit());
+    let print_consensus = matches.is_present("consensus");
+    let params = ConsensusParams::from_matches(matches, "");
+
+    let name = match matches.value_of("name") {
+        Some(n) => n.to_string(),
+        None => Path::new(fasta)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("TE")
+            // drop any label the name already carries.
+            .split('#')
+            .next()
+            .unwrap()
+            .to_string(),
+    };
+
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let sequences = alignment.matrix.len();
+    let (consensus, columns) = trimmed_consensus_columns(&alignment, &params);
+
+    let mut evidence = Evidence::new(
+        &consensus,
+        find_tir(&consensus, tir_window, tir_min_len, tir_min_iden),
+        find_ltr(&consensus, ltr_window, ltr_min_len, ltr_min_iden),
+        sequences,
+    );
+    match evidence.element() {
+        // look for the TSD's right at the element boundaries.
+        Some(element) => {
+            let tsds = boundary_tsds(
+                &alignment,
+                &columns,
+                element,
+                tsd_min_window,
+                tsd_max_window,
+                4,
+            );
+            let tsds: Vec<String> = tsds.into_iter().flatten().collect();
+            evidence.tsd_length = most_common(tsds.iter().map(|t| t.len()));
+            evidence.tsd = most_common(tsds);
+        }
+        // otherwise, the shared k-mers at the ends of the untrimmed alignment.
+        None => {
+            let tsds = alignment
+                .to_tsd_hash(tsd_len, tsd_min_window, tsd_max_window)
+                .merge(true)
+                .unwrap();
+            evidence.tsd = top_tsd(&tsds);
+            evidence.tsd_length = evidence.tsd.as_ref().map(|(t, c)| (t.len(), *c));
+        }
+    }
+    // Helitrons have neither, so are looked for separately.
+    evidence.helitron = Some(find_helitron(
+        &alignment,
+        &consensus,
+        &columns,
+        &HelitronParams::default(),
+        (tsd_len, tsd_min_window, tsd_max_window),
+    ));
+
+    let classification = classify_evidence(&evidence);
+    let label = format!("{}#{}", name, classification.label());
+
+    if print_consensus {
+        println!(">{}\n{}", label, WriteSequence(consensus));
+        return;
+    }
+    println!("Name\tClassification\tConfidence\tRules");
+    println!(
+        "{}\t{}\t{:.2}\t{}",
+        label,
+        classification.label(),
+        classification.confidence,
+        if classification.rules.is_empty() {
+            "-".to_string()
+        } else {
+            classification.rules.join("; ")
+        }
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::revcomp::reverse_complement;
+
+    // bases with no structure to speak of.
+    fn body(length: usize) -> Vec<u8> {
+        let mut state: u64 = 42;
+        (0..length)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 33) as usize % 4]
+            })
+            .collect()
+    }
+
+    fn classify(consensus: &[u8], tsd: Option<(&str, usize)>) -> Classification {
+        let mut evidence = Evidence::new(
+            consensus,
+            find_tir(consensus, 50, 15, 0.8),
+            find_ltr(consensus, 100, 20, 0.8),
+            10,
+        );
+        evidence.tsd = tsd.map(|(t, count)| (t.to_string(), count));
+        evidence.tsd_length = tsd.map(|(t, count)| (t.len(), count));
+        classify_evidence(&evidence)
+    }
+
+    #[test]
+    fn tir_and_ta_is_tc1_mariner() {
+        let arm = b"GAGGGTTGCAACTGCCATGA";
+        let consensus = [&arm[..], &body(300), &reverse_complement(arm)].concat();
+        let call = classify(&consensus, Some(("TA", 8)));
+        assert_eq!(call.label(), "DNA/TcMar");
+        assert_eq!(call.confidence, 1.0);
+        // a TSD in one copy of ten is not enough.
+        assert_eq!(classify(&consensus, Some(("TA", 1))).label(), "Unknown");
+    }
+
+    #[test]
+    fn tg_ca_ltrs() {
+        let ltr = b"TGAAGCTTCCGATGCAATCCGGTACTGGATCGCA";
+        let consensus = [&ltr[..], &body(300), &ltr[..]].concat();
+        let call = classify(&consensus, Some(("ACGTA", 6)));
+        assert_eq!(call.label(), "LTR/Unknown");
+        assert!(call.rules.contains(&"TG...CA LTR termini".to_string()));
+    }
+
+    #[test]
+    fn poly_a_tail_is_a_line() {
+        let consensus = [body(300), b"A".repeat(20)].concat();
+        let call = classify(&consensus, None);
+        assert_eq!(call.label(), "LINE/Unknown");
+        assert!(call.rules[0].ends_with("bp (A)n 3' tail"));
+    }
+
+    #[test]
+    fn no_evidence_is_unknown() {
+        let call = classify(&body(300), None);
+        assert_eq!(call.label(), "Unknown");
+        assert!(call.rules.is_empty());
+    }
+}
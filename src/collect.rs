@@ -0,0 +1,318 @@
+use clap::value_t;
+use std::collections::BTreeSet;
+use std::fs;
+use std::str::FromStr;
+
+use crate::extend::{extend_locus, fetch_locus, open_genome};
+use crate::utils::locus::{Locus, Strand};
+
+// the first step of curation: blast the consensus back to the genome,
+// and pull out the best copies, with flanks, to align. the output
+// goes on to ttc, html and friends once aligned.
+
+/// The formats hits can be read in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HitFormat {
+    /// BLAST tabular, `-outfmt 6`, with the default columns.
+    Blast,
+    /// minimap2 style PAF, with the genome as the target.
+    Paf,
+}
+
+impl FromStr for HitFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blast" => Ok(HitFormat::Blast),
+            "paf" => Ok(HitFormat::Paf),
+            _ => Err(format!("Unknown hit format: {}", s)),
+        }
+    }
+}
+
+/// A hit of the consensus in the genome.
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub query: String,
+    /// where in the genome, one based and inclusive.
+    pub locus: Locus,
+    /// proportion of identical bases in the alignment.
+    pub identity: f64,
+    /// alignment length.
+    pub length: usize,
+    /// BLAST only.
+    pub evalue: Option<f64>,
+    /// bit score for BLAST, number of matching bases for PAF.
+    pub score: f64,
+}
+
+/// Guess the format from the first hit: PAF has a strand in the fifth
+/// column, where BLAST has the number of mismatches.
+pub fn detect_hit_format(contents: &str) -> HitFormat {
+    let first = contents
+        .lines()
+        .find(|l| !l.trim().is_empty() && !l.starts_with('#'));
+    match first.and_then(|l| l.split('\t').nth(4)) {
+        Some("+") | Some("-") => HitFormat::Paf,
+        _ => HitFormat::Blast,
+    }
+}
+
+fn parse_blast(fields: &[&str]) -> Option<Hit> {
+    if fields.len() < 12 {
+        return None;
+    }
+    let (a, b): (usize, usize) = (fields[8].parse().ok()?, fields[9].parse().ok()?);
+    Some(Hit {
+        query: fields[0].to_string(),
+        locus: Locus {
+            contig: fields[1].to_string(),
+            start: a.min(b),
+            end: a.max(b),
+            // subject coordinates are backwards for minus strand hits.
+            strand: if a > b { Strand::Minus } else { Strand::Plus },
+        },
+        identity: fields[2].parse::<f64>().ok()? / 100.0,
+        length: fields[3].parse().ok()?,
+        evalue: Some(fields[10].parse().ok()?),
+        score: fields[11].parse().ok()?,
+    })
+}
+
+fn parse_paf(fields: &[&str]) -> Option<Hit> {
+    if fields.len() < 12 {
+        return None;
+    }
+    let matches: usize = fields[9].parse().ok()?;
+    let length: usize = fields[10].parse().ok()?;
+    // PAF is zero based and half open.
+    let start: usize = fields[7].parse().ok()?;
+    Some(Hit {
+        query: fields[0].to_string(),
+        locus: Locus {
+            contig: fields[5].to_string(),
+            start: start + 1,
+            end: fields[8].parse().ok()?,
+            strand: if fields[4] == "-" {
+                Strand::Minus
+            } else {
+                Strand::Plus
+            },
+        },
+        identity: matches as f64 / length as f64,
+        length,
+        evalue: None,
+        score: matches as f64,
+    })
+}
+
+/// Parse the hits, skipping comment and malformed lines.
+pub fn parse_hits(contents: &str, format: HitFormat) -> Vec<Hit> {
+    contents
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .filter_map(|l| {
+            let fields: Vec<&str> = l.split('\t').collect();
+            let hit = match format {
+                HitFormat::Blast => parse_blast(&fields),
+                HitFormat::Paf => parse_paf(&fields),
+            };
+            if hit.is_none() {
+                eprintln!("[-]\tCould not parse the hit: {}", l);
+            }
+            hit
+        })
+        .collect()
+}
+
+/// Merge hits on the same contig and strand which overlap, or are within
+/// `distance` bases of each other. The length is that of the merged span,
+/// scores are summed, and identity is the mean weighted by the bases each
+/// hit adds to the span.
+pub fn merge_hits(mut hits: Vec<Hit>, distance: usize) -> Vec<Hit> {
+    hits.sort_by(|a, b| {
+        a.locus
+            .contig
+            .cmp(&b.locus.contig)
+            .then((a.locus.strand == Strand::Minus).cmp(&(b.locus.strand == Strand::Minus)))
+            .then(a.locus.start.cmp(&b.locus.start))
+    });
+
+    let mut merged: Vec<Hit> = Vec::new();
+    for hit in hits {
+        match merged.last_mut() {
+            Some(last)
+                if last.locus.contig == hit.locus.contig
+                    && last.locus.strand == hit.locus.strand
+                    && hit.locus.start <= last.locus.end + distance + 1 =>
+            {
+                // overlapping bases are only counted once.
+                let end = last.locus.end.max(hit.locus.end);
+                let added = (end - last.locus.end).min(hit.locus.len());
+                last.identity = (last.identity * last.length as f64 + hit.identity * added as f64)
+                    / (last.length + added) as f64;
+                last.locus.end = end;
+                last.length = last.locus.len();
+                last.score += hit.score;
+                last.evalue = match (last.evalue, hit.evalue) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+            }
+            _ => merged.push(hit),
+        }
+    }
+    merged
+}
+
+pub fn collect(matches: &clap::ArgMatches) {
+    let hits_path = matches.value_of("hits").unwrap();
+    let genome = matches.value_of("genome").unwrap();
+    let query = matches.value_of("query");
+    let min_identity = value_t!(matches.value_of("min_identity"), f64).unwrap_or_else(|e| e.exit());
+    let min_length = value_t!(matches.value_of("min_length"), usize).unwrap_or_else(|e| e.exit());
+    let max_evalue = value_t!(matches.value_of("max_evalue"), f64).unwrap_or_else(|e| e.exit());
+    let merge_distance =
+        value_t!(matches.value_of("merge_distance"), usize).unwrap_or_else(|e| e.exit());
+    let top = value_t!(matches.value_of("top"), usize).unwrap_or_else(|e| e.exit());
+    let flank = value_t!(matches.value_of("flank"), usize).unwrap_or_else(|e| e.exit());
+
+    let contents = fs::read_to_string(hits_path).expect("[-]\tPath invalid.");
+    let format = match matches.value_of("format").unwrap() {
+        "auto" => detect_hit_format(&contents),
+        f => f.parse().unwrap(),
+    };
+    let hits = parse_hits(&contents, format);
+
+    // hits of one consensus make one family.
+    let queries: BTreeSet<&str> = hits.iter().map(|h| h.query.as_str()).collect();
+    let query = match query {
+        Some(q) => q.to_string(),
+        None if queries.len() <= 1 => queries.into_iter().next().unwrap_or("").to_string(),
+        None => {
+            eprintln!(
+                "[-]\tHits of {} consensus sequences, choose one with --query: {}",
+                queries.len(),
+                queries.into_iter().collect::<Vec<_>>().join(", ")
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let total = hits.len();
+    let passed: Vec<Hit> = hits
+        .into_iter()
+        .filter(|h| h.query == query)
+        .filter(|h| h.identity >= min_identity && h.length >= min_length)
+        .filter(|h| h.evalue.map_or(true, |e| e <= max_evalue))
+        .collect();
+    let passed_count = passed.len();
+    let mut merged = merge_hits(passed, merge_distance);
+    merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+    eprintln!(
+        "[+]\t{} of {} hits passed the filters, merged into {} copies.",
+        passed_count,
+        total,
+        merged.len()
+    );
+
+    let (mut reader, contig_lengths) = open_genome(genome);
+    let mut written = 0;
+    for hit in merged.iter().take(top) {
+        let contig_length = match contig_lengths.get(&hit.locus.contig) {
+            Some(l) => *l,
+            None => {
+                eprintln!(
+                    "[-]\tContig {} is not in the genome. Skipping.",
+                    hit.locus.contig
+                );
+                continue;
+            }
+        };
+        let locus = extend_locus(&hit.locus, flank, flank, contig_length);
+        let seq = fetch_locus(&mut reader, &locus).expect("[-]\tCould not read from the genome.");
+        println!(">{}\n{}", locus, String::from_utf8_lossy(&seq));
+        written += 1;
+    }
+
+    eprintln!(
+        "[+]\t{} copies of {} written, with up to {} bp flanks. Align them, and then on to ttc.",
+        written, query, flank
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLAST: &str = "\
+# BLASTN 2.12.0+
+rnd-1_family-1\tchr1\t90.00\t100\t10\t0\t1\t100\t1100\t1001\t1e-30\t150
+rnd-1_family-1\tchr1\t80.00\t100\t20\t0\t1\t100\t1051\t1150\t1e-20\t120
+";
+
+    fn hit(start: usize, end: usize, identity: f64, strand: Strand) -> Hit {
+        Hit {
+            query: "q".to_string(),
+            locus: Locus {
+                contig: "chr1".to_string(),
+                start,
+                end,
+                strand,
+            },
+            identity,
+            length: end - start + 1,
+            evalue: None,
+            score: 1.0,
+        }
+    }
+
+    #[test]
+    fn parses_blast() {
+        assert_eq!(detect_hit_format(BLAST), HitFormat::Blast);
+        let hits = parse_hits(BLAST, HitFormat::Blast);
+        assert_eq!(hits.len(), 2);
+        assert_eq!((hits[0].locus.start, hits[0].locus.end), (1001, 1100));
+        assert_eq!(hits[0].locus.strand, Strand::Minus);
+        assert_eq!((hits[0].identity, hits[0].length), (0.9, 100));
+        assert_eq!((hits[0].evalue, hits[0].score), (Some(1e-30), 150.0));
+        assert_eq!(hits[1].locus.strand, Strand::Plus);
+    }
+
+    #[test]
+    fn parses_paf() {
+        let paf = "q\t500\t0\t500\t-\tchr2\t10000\t999\t1499\t450\t500\t60\n";
+        assert_eq!(detect_hit_format(paf), HitFormat::Paf);
+        let hits = parse_hits(paf, HitFormat::Paf);
+        assert_eq!((hits[0].locus.start, hits[0].locus.end), (1000, 1499));
+        assert_eq!(hits[0].locus.strand, Strand::Minus);
+        assert_eq!((hits[0].identity, hits[0].score), (0.9, 450.0));
+        assert!(parse_hits("q\tchr1\t90.0\n", HitFormat::Blast).is_empty());
+    }
+
+    #[test]
+    fn merges_overlapping_hits() {
+        let hits = vec![
+            hit(1, 100, 1.0, Strand::Plus),
+            hit(51, 150, 0.5, Strand::Plus),
+            hit(161, 200, 1.0, Strand::Plus),
+            hit(101, 120, 1.0, Strand::Minus),
+        ];
+        let merged = merge_hits(hits, 5);
+        assert_eq!(merged.len(), 3);
+        let (first, last) = (&merged[0], &merged[1]);
+        assert_eq!(
+            (first.locus.start, first.locus.end, first.length),
+            (1, 150, 150)
+        );
+        // the second hit only adds 50 bases.
+        assert!((first.identity - 125.0 / 150.0).abs() < 1e-9);
+        assert_eq!((last.locus.start, last.locus.end), (161, 200));
+        assert_eq!(merged[2].locus.strand, Strand::Minus);
+        // within the distance, so merged.
+        let merged = merge_hits(merged, 10);
+        assert_eq!((merged[0].locus.end, merged[0].length), (200, 200));
+        assert_eq!(merged[0].score, 3.0);
+    }
+}
@@ -1,8 +1,10 @@
-use bio::io::fasta;
-use std::collections::HashMap;
+use clap::{value_t, Arg};
 use std::fmt::{Display, Error, Formatter};
+use std::str::FromStr;
 
-use crate::utils::alignment::{Alignment, Sequence};
+use crate::utils::alignment::Alignment;
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+use crate::utils::profile::ColumnCounts;
 
 // with much help from https://github.com/Ninjani/rosalind/blob/master/s_cons/src/lib.rs
 
@@ -11,40 +13,24 @@ pub fn make_consensus(matches: &clap::ArgMatches) {
     let fasta = matches.value_of("fasta").unwrap();
     let name = matches.value_of("name").unwrap();
     let append = matches.is_present("append");
-
-    // do some read length checks.
-    // get the number of reads for free.
-    let mut check_read_lengths = Vec::new();
-    let mut read_number = 0i32;
-    // read in the fasta from file
-    let mut reader = fasta::Reader::from_file(fasta)
-        .expect("[-]\tPath invalid.")
-        .records();
-    while let Some(Ok(record)) = reader.next() {
-        read_number += 1;
-        check_read_lengths.push(record.seq().len());
-    }
-    let check_lens = is_all_same(&check_read_lengths);
-
-    if !check_lens {
-        panic!("[-]\tAll sequences in the fasta file are not the same length.")
-    }
-
-    let reader = fasta::Reader::from_file(fasta).expect("[-]\tPath invalid.");
+    let params = ConsensusParams::from_matches(matches, "");
 
     // collect sequences into memory
     // should be fine for small(ish) alignments
-    let mut records = Alignment::new();
-    for result in reader.records() {
-        let record = result.expect("[-]\tError during fasta record parsing.");
-        let id = record.id().to_owned();
-        let sequence = record.seq().to_owned();
-        records.add_sequence(Sequence { name: id, sequence });
+    let records = read_alignment_as(fasta, format_from_matches(matches));
+    let read_number = records.matrix.len() as i32;
+
+    // do some read length checks.
+    if !records.is_aligned() {
+        eprintln!(
+            "[-]\tAll sequences in the fasta file are not the same length. See `reputils stats`."
+        );
+        std::process::exit(1);
     }
 
     // containing the frequencies of each nucleotide at each column
     let profile = records.get_profile();
-    let consensus = get_consensus(profile, read_number);
+    let consensus = get_consensus(&profile, read_number, &params);
 
     if !append {
         println!(">{}\n{}", name, WriteSequence(consensus));
@@ -56,159 +42,309 @@ pub fn make_consensus(matches: &clap::ArgMatches) {
     }
 }
 
-/// Get consensus sequence from a profile
-pub fn get_consensus(profile: Vec<HashMap<u8, usize>>, read_number: i32) -> Vec<u8> {
-    // initiate consensus
-    let mut consensus = Vec::with_capacity(profile.len());
+/// How the winning base in a column is chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsensusRule {
+    /// The most frequent base wins, as long as it is above the threshold.
+    Plurality,
+    /// As plurality, but the winning base must also be in more than half the sequences.
+    Majority,
+}
 
-    let minority = 0.4;
-    // iterate over the vector of hashmaps
-    for counts in profile.iter() {
-        // frequencies of A, C, G, T's
-        let g_c = counts.get(&71).unwrap_or(&0) + counts.get(&103).unwrap_or(&0); // 71 == G; 103 == g
-        let c_c = counts.get(&67).unwrap_or(&0) + counts.get(&99).unwrap_or(&0); // 67 == C; 99 == c
-        let a_c = counts.get(&65).unwrap_or(&0) + counts.get(&97).unwrap_or(&0); // 65 == A; 97 == a
-        let t_c = counts.get(&84).unwrap_or(&0) + counts.get(&116).unwrap_or(&0); // 84 == T; 116 == t
-
-        // deal with single best variants first
-        // this is a very crazy way to do this. But seems to work.
-        if g_c > c_c && g_c > a_c && g_c > t_c && g_c as f32 > (minority * read_number as f32) {
-            consensus.push(71u8);
-        } else if c_c > g_c
-            && c_c > a_c
-            && c_c > t_c
-            && c_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(67u8);
-        } else if a_c > g_c
-            && a_c > c_c
-            && a_c > t_c
-            && a_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(65u8);
-        } else if t_c > g_c
-            && t_c > a_c
-            && t_c > c_c
-            && t_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(84u8);
-        }
-        // now deal with duals (IUPAC)
-        // g/t == k
-        else if g_c > c_c
-            && g_c > a_c
-            && g_c == t_c
-            && g_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(75u8);
-        }
-        // g/c == s
-        else if g_c > a_c
-            && g_c > t_c
-            && g_c == c_c
-            && g_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(83u8);
-        }
-        // g/a == r
-        else if g_c > c_c
-            && g_c > t_c
-            && g_c == a_c
-            && g_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(82u8);
-        }
-        // a/c == m
-        else if a_c > g_c
-            && a_c > t_c
-            && a_c == c_c
-            && a_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(77u8);
-        }
-        // a/t == w
-        else if a_c > g_c
-            && a_c > c_c
-            && a_c == t_c
-            && a_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(87u8);
-        }
-        // c/t == y
-        else if c_c > g_c
-            && c_c > a_c
-            && c_c == t_c
-            && c_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(89u8);
-        }
-        // a/c/g == v
-        else if a_c > t_c
-            && a_c == c_c
-            && a_c == g_c
-            && a_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(86u8);
+impl FromStr for ConsensusRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plurality" => Ok(ConsensusRule::Plurality),
+            "majority" => Ok(ConsensusRule::Majority),
+            _ => Err(format!("{} is not a consensus rule.", s)),
         }
-        // a/c/t == h
-        else if a_c > g_c
-            && a_c == c_c
-            && a_c == t_c
-            && a_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(72u8);
+    }
+}
+
+/// What to do with columns made up mostly of gaps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GapColumns {
+    /// Gaps only dilute the support for a base; a column with no supported base is a gap.
+    Dilute,
+    /// A column is a gap whenever gaps outnumber the best base.
+    Majority,
+    /// Never emit a gap; unsupported columns get the low support symbol.
+    Never,
+}
+
+impl FromStr for GapColumns {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dilute" => Ok(GapColumns::Dilute),
+            "majority" => Ok(GapColumns::Majority),
+            "never" => Ok(GapColumns::Never),
+            _ => Err(format!("{} is not a gap column treatment.", s)),
         }
-        // a/g/t == d
-        else if a_c > c_c
-            && a_c == g_c
-            && a_c == t_c
-            && a_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(68u8);
+    }
+}
+
+/// Parameters for calling a consensus from a profile.
+/// The default reproduces the original hard coded caller.
+#[derive(Debug, Clone, Copy)]
+pub struct ConsensusParams {
+    pub rule: ConsensusRule,
+    /// Proportion of sequences the best base must exceed.
+    pub threshold: f32,
+    /// Proportion of sequences a base must exceed for an
+    /// unsupported column to get the low support symbol, rather than a gap.
+    pub low_threshold: f32,
+    /// Emit IUPAC codes for ties, otherwise pick the first of A, C, G, T.
+    pub iupac: bool,
+    pub gaps: GapColumns,
+    /// Symbol for columns with weak support.
+    pub low_symbol: u8,
+    /// Measure support against the sequences which are not a gap in
+    /// each column, rather than against all sequences.
+    pub gap_aware: bool,
+    /// With `gap_aware`, the proportion of sequences which must not be
+    /// a gap for a column to be called at all. Others are a gap.
+    pub min_occupancy: f32,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        ConsensusParams {
+            rule: ConsensusRule::Plurality,
+            threshold: 0.4,
+            low_threshold: 0.3,
+            iupac: true,
+            gaps: GapColumns::Dilute,
+            low_symbol: b'?',
+            gap_aware: false,
+            min_occupancy: 0.1,
         }
-        // c/g/t == b
-        else if c_c > a_c
-            && c_c == g_c
-            && c_c == t_c
-            && c_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(66u8);
+    }
+}
+
+impl ConsensusParams {
+    /// Parse the consensus options from the command line. `prefix` is
+    /// prepended to each argument name (e.g. "con_" in html).
+    pub fn from_matches(matches: &clap::ArgMatches, prefix: &str) -> Self {
+        let arg = |name: &str| format!("{}{}", prefix, name);
+
+        let rule =
+            value_t!(matches.value_of(&arg("rule")), ConsensusRule).unwrap_or_else(|e| e.exit());
+        let threshold =
+            value_t!(matches.value_of(&arg("threshold")), f32).unwrap_or_else(|e| e.exit());
+        let low_threshold =
+            value_t!(matches.value_of(&arg("low_threshold")), f32).unwrap_or_else(|e| e.exit());
+        let iupac = !matches.is_present(arg("no_iupac"));
+        let gaps =
+            value_t!(matches.value_of(&arg("gaps")), GapColumns).unwrap_or_else(|e| e.exit());
+        let low_symbol =
+            value_t!(matches.value_of(&arg("low_symbol")), char).unwrap_or_else(|e| e.exit());
+        // it goes into the consensus as a byte.
+        if !low_symbol.is_ascii() {
+            eprintln!("[-]\t--{} must be an ASCII character.", arg("low_symbol"));
+            std::process::exit(1);
         }
-        // a/c/g/t == N
-        // should this be N????
-        else if c_c == a_c
-            && c_c == g_c
-            && c_c == t_c
-            && c_c as f32 > (minority * read_number as f32)
-        {
-            consensus.push(78u8);
+        let low_symbol = low_symbol as u8;
+        let gap_aware = matches.is_present(arg("gap_aware"));
+        let min_occupancy =
+            value_t!(matches.value_of(&arg("min_occupancy")), f32).unwrap_or_else(|e| e.exit());
+
+        ConsensusParams {
+            rule,
+            threshold,
+            low_threshold,
+            iupac,
+            gaps,
+            low_symbol,
+            gap_aware,
+            min_occupancy,
         }
-        // add ? if any majority nucleotide frequency below 0.5
-        // but if < 0.2, then put a dash
-        // these numbers can of course be changed.
-        else if (c_c as f32) < (minority * read_number as f32)
-            && (c_c as f32) > (0.3 * read_number as f32)
-            || (g_c as f32) < (minority * read_number as f32)
-                && (g_c as f32) > (0.3 * read_number as f32)
-            || (a_c as f32) < (minority * read_number as f32)
-                && (a_c as f32) > (0.3 * read_number as f32)
-            || (t_c as f32) < (minority * read_number as f32)
-                && (t_c as f32) > (0.3 * read_number as f32)
-        {
-            consensus.push(63u8);
+    }
+}
+
+/// The options `ConsensusParams::from_matches` reads, with `prefix`
+/// prepended to each name.
+pub fn consensus_args(prefix: &str) -> Vec<Arg<'static, 'static>> {
+    // clap keeps the names for as long as the program runs.
+    let name =
+        |name: &str| -> &'static str { Box::leak(format!("{}{}", prefix, name).into_boxed_str()) };
+    vec![
+        Arg::with_name(name("rule"))
+            .long(name("rule"))
+            .takes_value(true)
+            .required(true)
+            .default_value("plurality")
+            .possible_values(&["plurality", "majority"])
+            .help("Call the most frequent base, or additionally require it in over half the sequences."),
+        Arg::with_name(name("threshold"))
+            .long(name("threshold"))
+            .takes_value(true)
+            .required(true)
+            .default_value("0.4")
+            .help("Proportion of sequences the best base must exceed to be called."),
+        Arg::with_name(name("low_threshold"))
+            .long(name("low_threshold"))
+            .takes_value(true)
+            .required(true)
+            .default_value("0.3")
+            .help("Unsupported columns with a base above this proportion get the low support symbol, otherwise a gap."),
+        Arg::with_name(name("no_iupac"))
+            .long(name("no_iupac"))
+            .help("Pick the first best base (A, C, G, T order) instead of emitting IUPAC codes for ties."),
+        Arg::with_name(name("gaps"))
+            .long(name("gaps"))
+            .takes_value(true)
+            .required(true)
+            .default_value("dilute")
+            .possible_values(&["dilute", "majority", "never"])
+            .help("Gap dominated columns: gaps only dilute support, are a gap when gaps outnumber the best base, or are never a gap."),
+        Arg::with_name(name("low_symbol"))
+            .long(name("low_symbol"))
+            .takes_value(true)
+            .required(true)
+            .default_value("?")
+            .help("Symbol emitted for columns with weak support."),
+        Arg::with_name(name("gap_aware"))
+            .long(name("gap_aware"))
+            .help("Measure support against the sequences which are not a gap in each column."),
+        Arg::with_name(name("min_occupancy"))
+            .long(name("min_occupancy"))
+            .takes_value(true)
+            .required(true)
+            .default_value("0.1")
+            .help("With gap_aware, the proportion of sequences which must not be a gap for a column to be called."),
+    ]
+}
+
+/// Get consensus sequence from a profile
+pub fn get_consensus(
+    profile: &[ColumnCounts],
+    read_number: i32,
+    params: &ConsensusParams,
+) -> Vec<u8> {
+    // initiate consensus
+    let mut consensus = Vec::with_capacity(profile.len());
+
+    let n = read_number as f32;
+    // iterate over the columns of the profile
+    for counts in profile.iter() {
+        // frequencies of A, C, G, T's
+        let [a_c, c_c, g_c, t_c] = counts.bases;
+        let gap_c = counts.gap;
+
+        if params.gap_aware {
+            // only the sequences present in this column count
+            let occupied = counts.occupied();
+            if (occupied as f32) < params.min_occupancy * n || occupied == 0 {
+                consensus.push(b'-');
+            } else {
+                consensus.push(call_column(
+                    [a_c, c_c, g_c, t_c],
+                    gap_c,
+                    occupied as f32,
+                    params,
+                ));
+            }
         } else {
-            consensus.push(45u8);
+            consensus.push(call_column([a_c, c_c, g_c, t_c], gap_c, n, params));
         }
     }
     consensus
 }
 
-fn is_all_same(arr: &[usize]) -> bool {
-    if arr.is_empty() {
-        return true;
+// call a single column from the counts of A, C, G, T and gaps,
+// where `n` is the number of sequences support is measured against.
+fn call_column(bases: [usize; 4], gaps: usize, n: f32, params: &ConsensusParams) -> u8 {
+    let best = *bases.iter().max().unwrap();
+    // which bases are tied for best
+    let tied = [
+        bases[0] == best,
+        bases[1] == best,
+        bases[2] == best,
+        bases[3] == best,
+    ];
+
+    let supported = best as f32 > params.threshold * n
+        && (params.rule == ConsensusRule::Plurality || best as f32 > 0.5 * n);
+
+    if params.gaps == GapColumns::Majority && gaps > best {
+        return b'-';
+    }
+
+    if supported {
+        if params.iupac {
+            iupac(tied)
+        } else {
+            // pick the first of the best bases
+            b"ACGT"[tied.iter().position(|&t| t).unwrap()]
+        }
+    }
+    // add ? if any nucleotide frequency is between the low
+    // threshold and the threshold, otherwise put a dash.
+    else if params.gaps == GapColumns::Never
+        || bases
+            .iter()
+            .any(|&c| (c as f32) < params.threshold * n && (c as f32) > params.low_threshold * n)
+    {
+        params.low_symbol
+    } else {
+        b'-'
     }
-    let first = arr[0];
-    arr.iter().all(|&item| item == first)
+}
+
+// IUPAC code for a set of bases in A, C, G, T order.
+fn iupac(bases: [bool; 4]) -> u8 {
+    match bases {
+        [true, false, false, false] => b'A',
+        [false, true, false, false] => b'C',
+        [false, false, true, false] => b'G',
+        [false, false, false, true] => b'T',
+        [false, false, true, true] => b'K',
+        [false, true, true, false] => b'S',
+        [true, false, true, false] => b'R',
+        [true, true, false, false] => b'M',
+        [true, false, false, true] => b'W',
+        [false, true, false, true] => b'Y',
+        [true, true, true, false] => b'V',
+        [true, true, false, true] => b'H',
+        [true, false, true, true] => b'D',
+        [false, true, true, true] => b'B',
+        _ => b'N',
+    }
+}
+
+/// Quickly trim an alignment with the `ttc` defaults, call the
+/// consensus, and remove the gaps from it. Used by the subcommands
+/// which look for structure in the consensus (TIR's, LTR's, ORF's).
+pub fn trimmed_consensus(alignment: Alignment, params: &ConsensusParams) -> Vec<u8> {
+    trimmed_consensus_columns(&alignment, params).0
+}
+
+/// As `trimmed_consensus`, but also returns the column of the untrimmed
+/// alignment each base of the consensus was called from.
+pub fn trimmed_consensus_columns(
+    alignment: &Alignment,
+    params: &ConsensusParams,
+) -> (Vec<u8>, Vec<usize>) {
+    let read_number = alignment.matrix.len() as i32;
+
+    // the one profile for both the trimming and the consensus.
+    let profile = alignment.get_profile();
+    let alignment_blocks = profile.find_blocks(0.1, 0.8);
+    let (start, end) = alignment_blocks.trim_bounds(alignment.matrix[0].len(), 15, 1);
+
+    let consensus = get_consensus(&profile[start..end], read_number, params);
+
+    // remove all gaps as they mess up any alignment
+    consensus
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, e)| e != 45)
+        .map(|(i, e)| (e, start + i))
+        .unzip()
 }
 
 // display for Vec<i32>
@@ -221,3 +357,80 @@ impl Display for WriteSequence {
         write!(f, "{}", sequence)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the caller before ConsensusParams, from the counts of A, C, G, T.
+    fn hard_coded(a: usize, c: usize, g: usize, t: usize, read_number: usize) -> u8 {
+        let above = |x: usize| x as f32 > 0.4 * read_number as f32;
+        let low = |x: usize| {
+            (x as f32) < 0.4 * read_number as f32 && (x as f32) > 0.3 * read_number as f32
+        };
+        if g > c && g > a && g > t && above(g) {
+            b'G'
+        } else if c > g && c > a && c > t && above(c) {
+            b'C'
+        } else if a > g && a > c && a > t && above(a) {
+            b'A'
+        } else if t > g && t > a && t > c && above(t) {
+            b'T'
+        } else if g > c && g > a && g == t && above(g) {
+            b'K'
+        } else if g > a && g > t && g == c && above(g) {
+            b'S'
+        } else if g > c && g > t && g == a && above(g) {
+            b'R'
+        } else if a > g && a > t && a == c && above(a) {
+            b'M'
+        } else if a > g && a > c && a == t && above(a) {
+            b'W'
+        } else if c > g && c > a && c == t && above(c) {
+            b'Y'
+        } else if a > t && a == c && a == g && above(a) {
+            b'V'
+        } else if a > g && a == c && a == t && above(a) {
+            b'H'
+        } else if a > c && a == g && a == t && above(a) {
+            b'D'
+        } else if c > a && c == g && c == t && above(c) {
+            b'B'
+        } else if c == a && c == g && c == t && above(c) {
+            b'N'
+        } else if low(c) || low(g) || low(a) || low(t) {
+            b'?'
+        } else {
+            b'-'
+        }
+    }
+
+    #[test]
+    fn default_params_match_the_hard_coded_caller() {
+        for n in 1..=12 {
+            let mut columns = Vec::new();
+            let mut expected = Vec::new();
+            for a in 0..=n {
+                for c in 0..=n - a {
+                    for g in 0..=n - a - c {
+                        for t in 0..=n - a - c - g {
+                            columns.push(ColumnCounts {
+                                bases: [a, c, g, t],
+                                gap: n - a - c - g - t,
+                                ..Default::default()
+                            });
+                            expected.push(hard_coded(a, c, g, t, n));
+                        }
+                    }
+                }
+            }
+            let consensus = get_consensus(&columns, n as i32, &ConsensusParams::default());
+            assert_eq!(
+                String::from_utf8(consensus).unwrap(),
+                String::from_utf8(expected).unwrap(),
+                "{} sequences",
+                n
+            );
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+
+// the number of sequences which are not a gap in each column of the
+// alignment. jumps in coverage mark the ends of the TE in alignments
+// of BLAST hits extended into their flanks.
+
+/// Runs of columns with the same coverage, as (start, end, coverage),
+/// zero based and half open, as in a bedGraph.
+pub fn coverage_runs(coverage: &[usize]) -> Vec<(usize, usize, usize)> {
+    let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+    for (column, &count) in coverage.iter().enumerate() {
+        match runs.last_mut() {
+            Some((_, end, c)) if *c == count => *end = column + 1,
+            _ => runs.push((column, column + 1, count)),
+        }
+    }
+    runs
+}
+
+pub fn cov(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let format = matches.value_of("format").unwrap();
+    // the bedGraph chromosome is the alignment, named after the file.
+    let chrom = match matches.value_of("chrom") {
+        Some(c) => c.to_string(),
+        None => Path::new(fasta)
+            .file_stem()
+            .map_or("alignment".to_string(), |s| s.to_string_lossy().to_string()),
+    };
+
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let sequences = alignment.matrix.len();
+    let coverage = alignment.coverage();
+
+    match format {
+        "bedgraph" => {
+            println!(
+                "track type=bedGraph name=\"{}\" description=\"Non-gap sequences per column\"",
+                chrom
+            );
+            for (start, end, count) in coverage_runs(&coverage) {
+                println!("{}\t{}\t{}\t{}", chrom, start, end, count);
+            }
+        }
+        _ => {
+            println!("Column\tCoverage\tOccupancy");
+            for (column, count) in coverage.iter().enumerate() {
+                println!(
+                    "{}\t{}\t{:.3}",
+                    column + 1, // add one due to zero indexing.
+                    count,
+                    *count as f64 / sequences as f64
+                );
+            }
+        }
+    }
+
+    eprintln!(
+        "[+]\t{} columns, {} sequences. Coverage ranges from {} to {}.",
+        coverage.len(),
+        sequences,
+        coverage.iter().min().unwrap_or(&0),
+        coverage.iter().max().unwrap_or(&0)
+    );
+}
@@ -1,79 +1,120 @@
-use bio::io::fasta;
 use clap::value_t;
+use plotters::coord::Shift;
 use plotters::prelude::*;
 
-use crate::utils::alignment::{Alignment, Sequence};
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+use crate::utils::profile::DiversityWindow;
 
 pub fn diversity_windows(matches: &clap::ArgMatches) {
     let fasta = matches.value_of("fasta").unwrap();
     let window_size = value_t!(matches.value_of("window"), usize).unwrap_or_else(|e| e.exit());
     let window_step = value_t!(matches.value_of("step"), usize).unwrap_or_else(|e| e.exit());
+    let min_sites = value_t!(matches.value_of("min_sites"), f32).unwrap_or_else(|e| e.exit());
+    let popgen = matches.is_present("popgen");
     let plot = matches.is_present("plot");
     let dir = value_t!(matches.value_of("dir"), String).unwrap_or_else(|e| e.exit());
     let name = value_t!(matches.value_of("name"), String).unwrap_or_else(|e| e.exit());
 
-    let mut reader = fasta::Reader::from_file(fasta)
-        .expect("[-]\tPath invalid.")
-        .records();
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
 
-    let mut alignment = Alignment::new();
+    let data = alignment.div_windows(window_size, window_step, min_sites);
 
-    while let Some(Ok(record)) = reader.next() {
-        // alignment.push(std::str::from_utf8(record.seq()).unwrap().to_string());
-        alignment.add_sequence(Sequence {
-            name: record.id().to_string(),
-            sequence: record.seq().to_vec(),
-        });
+    // "-" where there were too few sites.
+    let format = |v: Option<f32>| v.map_or("-".to_string(), |v| format!("{:.4}", v));
+    if popgen {
+        println!("Start\tEnd\tPi\tPi per site\tSites\tSegregating sites\tWatterson's theta per site\tTajima's D");
+        for w in &data {
+            println!(
+                "{}\t{}\t{}\t{}",
+                w,
+                w.segregating_sites,
+                format(w.watterson),
+                format(w.tajimas_d)
+            );
+        }
+    } else {
+        println!("Start\tEnd\tPi\tPi per site\tSites");
+        for w in &data {
+            println!("{}", w);
+        }
     }
 
-    let data = alignment.div_windows(window_size, window_step, false);
-
     if plot {
         // do plot
-        plot_pi_windows(data, &dir, &name).expect("Couldn't make the plot :(");
+        plot_pi_windows(data, popgen, &dir, &name).expect("Couldn't make the plot :(");
     }
 }
 
 fn plot_pi_windows(
-    data: Vec<(usize, usize, f32)>,
+    data: Vec<DiversityWindow>,
+    popgen: bool,
     dir: &str,
     name: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // dimensions of the plot
-    let dims = (1280, 2 * 480);
-    // x from zero to length vec
-    // y from zero to max vec.
+    // one track for the diversity, and one each for theta and Tajima's D.
+    let mut tracks: Vec<(&str, Vec<Option<f32>>)> = vec![(
+        "Nucleotide diversity per site",
+        data.iter().map(|w| w.pi_per_site).collect(),
+    )];
+    if popgen {
+        tracks.push((
+            "Watterson's theta per site",
+            data.iter().map(|w| w.watterson).collect(),
+        ));
+        tracks.push(("Tajima's D", data.iter().map(|w| w.tajimas_d).collect()));
+    }
 
-    let ymax = data
-        .iter()
-        .map(|(_a, _b, c)| c)
-        .fold(0.0f32, |max, &val| if val > max { val } else { max });
-    let xmax = data[data.len() - 1].0;
+    // dimensions of the plot
+    let dims = (1280, if popgen { 3 * 480 } else { 2 * 480 });
+    let starts: Vec<f32> = data.iter().map(|w| w.start as f32).collect();
+    let xmax = data[data.len() - 1].start;
 
     let path = format!("{}/{}.png", dir, name);
 
     let root = BitMapBackend::new(&path, (dims.0, dims.1)).into_drawing_area();
     root.fill(&WHITE)?;
     let root = root.margin(10, 10, 10, 10);
+    for (area, (y_desc, values)) in root.split_evenly((tracks.len(), 1)).iter().zip(tracks) {
+        plot_track(area, &starts, &values, xmax as f32, y_desc)?;
+    }
+
+    Ok(())
+}
+
+// a line along the alignment, broken over windows with no value.
+fn plot_track(
+    area: &DrawingArea<BitMapBackend, Shift>,
+    starts: &[f32],
+    values: &[Option<f32>],
+    xmax: f32,
+    y_desc: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // x from zero to length vec
+    // y from the lowest (Tajima's D can be negative) to the highest value.
+    let ymin = values.iter().flatten().fold(0.0f32, |min, &v| min.min(v));
+    let ymax = values.iter().flatten().fold(0.0f32, |max, &v| max.max(v));
+
     // After this point, we should be able to draw construct a chart context
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(area)
         .x_label_area_size(20)
         .y_label_area_size(40)
         .set_label_area_size(LabelAreaPosition::Left, (8).percent())
         .set_label_area_size(LabelAreaPosition::Bottom, (8).percent())
-        // zero first value needs to be replaced by a minimum I think... for the Y
-        .build_cartesian_2d(0f32..xmax as f32, 0f32..ymax as f32)?;
+        .build_cartesian_2d(0f32..xmax, ymin..ymax)?;
 
     chart
         .configure_mesh()
-        .y_desc("Nucleotide diversity")
+        .y_desc(y_desc)
         .x_desc("Length along alignment")
         .label_style(TextStyle::from(("sans-serif", 25)))
         .draw()?;
 
-    let line_data: Vec<(f32, f32)> = data.iter().map(|(a, _b, c)| (*a as f32, *c)).collect();
-
-    chart.draw_series(LineSeries::new(line_data, &BLACK))?;
+    let points: Vec<(f32, Option<f32>)> =
+        starts.iter().copied().zip(values.iter().copied()).collect();
+    for segment in points.split(|(_, v)| v.is_none()) {
+        let line_data: Vec<(f32, f32)> = segment.iter().map(|(x, v)| (*x, v.unwrap())).collect();
+        chart.draw_series(LineSeries::new(line_data, &BLACK))?;
+    }
 
     Ok(())
 }
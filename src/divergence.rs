@@ -0,0 +1,160 @@
+use crate::con::{get_consensus, ConsensusParams};
+use crate::utils::alignment::Alignment;
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+
+// how far each copy has diverged from the consensus, a proxy for the
+// age of the insertions (as RepeatMasker's calcDivergenceFromAlign.pl).
+
+/// Divergence of one sequence from the consensus.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub name: String,
+    /// columns where both the consensus and the sequence are A, C, G or T.
+    pub sites: usize,
+    /// fractional with the CpG adjustment.
+    pub transitions: f64,
+    pub transversions: f64,
+    pub p_distance: f64,
+    /// Jukes-Cantor, undefined if the sequence is too diverged.
+    pub jukes_cantor: Option<f64>,
+    /// Kimura 2-parameter, undefined if the sequence is too diverged.
+    pub kimura: Option<f64>,
+}
+
+fn is_base(b: u8) -> bool {
+    matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T')
+}
+
+fn is_transition(a: u8, b: u8) -> bool {
+    matches!(
+        (a.to_ascii_uppercase(), b.to_ascii_uppercase()),
+        (b'A', b'G') | (b'G', b'A') | (b'C', b'T') | (b'T', b'C')
+    )
+}
+
+/// Jukes-Cantor distance from the p-distance.
+pub fn jukes_cantor(p: f64) -> Option<f64> {
+    let x = 1.0 - 4.0 * p / 3.0;
+    (x > 0.0).then(|| -0.75 * x.ln())
+}
+
+/// Kimura 2-parameter distance from the proportions of transitions
+/// and transversions.
+pub fn kimura(transitions: f64, transversions: f64) -> Option<f64> {
+    let (x, y) = (
+        1.0 - 2.0 * transitions - transversions,
+        1.0 - 2.0 * transversions,
+    );
+    (x > 0.0 && y > 0.0).then(|| -0.5 * x.ln() - 0.25 * y.ln())
+}
+
+/// The divergence of each sequence from `consensus`, which has a base
+/// (or a gap) for each column of the alignment. Gaps and ambiguity codes
+/// in either are skipped. With `cpg`, CpG sites in the consensus are
+/// treated as RepeatMasker does, as they mutate so often: two transitions
+/// count as one, and a single transition as a tenth of one.
+pub fn copy_divergence(alignment: &Alignment, consensus: &[u8], cpg: bool) -> Vec<Divergence> {
+    // the C's followed (ignoring gaps) by a G in the consensus, and
+    // those G's, with the column of the C.
+    let mut cpg_sites: Vec<Option<usize>> = vec![None; consensus.len()];
+    if cpg {
+        let columns: Vec<usize> = (0..consensus.len())
+            .filter(|&i| consensus[i] != b'-')
+            .collect();
+        for pair in columns.windows(2) {
+            if consensus[pair[0]].eq_ignore_ascii_case(&b'C')
+                && consensus[pair[1]].eq_ignore_ascii_case(&b'G')
+            {
+                cpg_sites[pair[0]] = Some(pair[0]);
+                cpg_sites[pair[1]] = Some(pair[0]);
+            }
+        }
+    }
+
+    alignment
+        .matrix
+        .iter()
+        .map(|record| {
+            let (mut sites, mut transitions, mut transversions) = (0, 0.0, 0.0);
+            // the last CpG site with a transition.
+            let mut last_site = None;
+            for (col, (&c, &s)) in consensus.iter().zip(&record.sequence).enumerate() {
+                if !is_base(c) || !is_base(s) {
+                    continue;
+                }
+                sites += 1;
+                if c.eq_ignore_ascii_case(&s) {
+                    continue;
+                }
+                if !is_transition(c, s) {
+                    transversions += 1.0;
+                } else if let Some(site) = cpg_sites[col] {
+                    // the second transition makes the site up to one.
+                    transitions += if last_site == Some(site) { 0.9 } else { 0.1 };
+                    last_site = Some(site);
+                } else {
+                    transitions += 1.0;
+                }
+            }
+            let n = sites.max(1) as f64;
+            let p_distance = (transitions + transversions) / n;
+            Divergence {
+                name: record.name.clone(),
+                sites,
+                transitions,
+                transversions,
+                p_distance,
+                jukes_cantor: jukes_cantor(p_distance),
+                kimura: kimura(transitions / n, transversions / n),
+            }
+        })
+        .collect()
+}
+
+// "-" where a distance is undefined.
+fn format_distance(d: Option<f64>) -> String {
+    d.map_or("-".to_string(), |d| format!("{:.4}", d))
+}
+
+pub fn divergence(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let cpg = matches.is_present("cpg");
+    let params = ConsensusParams::from_matches(matches, "");
+
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    if !alignment.is_aligned() {
+        eprintln!("[-]\tAll sequences in the fasta file are not the same length.");
+        std::process::exit(1);
+    }
+    let consensus = get_consensus(
+        &alignment.get_profile(),
+        alignment.matrix.len() as i32,
+        &params,
+    );
+    let divergences = copy_divergence(&alignment, &consensus, cpg);
+
+    println!("Sequence\tSites\tTransitions\tTransversions\tp-distance\tJukes-Cantor\tKimura");
+    for d in &divergences {
+        println!(
+            "{}\t{}\t{:.1}\t{:.1}\t{:.4}\t{}\t{}",
+            d.name,
+            d.sites,
+            d.transitions,
+            d.transversions,
+            d.p_distance,
+            format_distance(d.jukes_cantor),
+            format_distance(d.kimura)
+        );
+    }
+
+    let kimuras: Vec<f64> = divergences.iter().filter_map(|d| d.kimura).collect();
+    if !kimuras.is_empty() {
+        eprintln!(
+            "[+]\tMean Kimura divergence {:.4} over {} of {} sequences{}.",
+            kimuras.iter().sum::<f64>() / kimuras.len() as f64,
+            kimuras.len(),
+            divergences.len(),
+            if cpg { ", CpG adjusted" } else { "" }
+        );
+    }
+}
@@ -1,7 +1,7 @@
-use bio::io::fasta;
 use clap::value_t;
 use plotters::prelude::*;
 
+use crate::utils::formats::{format_from_matches, read_alignment_as};
 use crate::utils::windows::SeqWindows;
 
 // the code here is based on the laconic R version here:
@@ -17,18 +17,16 @@ pub fn dot(matches: &clap::ArgMatches) -> Result<(), Box<dyn std::error::Error +
     let nmatch = value_t!(matches.value_of("nmatches"), usize).unwrap_or_else(|e| e.exit());
     let dir = value_t!(matches.value_of("dir"), String).unwrap_or_else(|e| e.exit());
 
-    let reader = fasta::Reader::from_file(fasta).expect("[-]\tPath invalid.");
-
-    for record in reader.records() {
-        let record = record.expect("[-]\tError during fasta record parsing.");
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
 
+    for record in &alignment.matrix {
         // future Max filter out these gaps please
-        let seq_counter = SeqWindows::new(record.seq(), wsize, wstep);
+        let seq_counter = SeqWindows::new(&record.sequence, wsize, wstep);
         let no_its = seq_counter.count();
 
-        let seq_windows = SeqWindows::new(record.seq(), wsize, wstep);
+        let seq_windows = SeqWindows::new(&record.sequence, wsize, wstep);
 
-        let seq_windows_vec: Vec<&[u8]> = seq_windows.map(|s| s).collect();
+        let seq_windows_vec: Vec<&[u8]> = seq_windows.collect();
 
         // Base 1d array
         let mut matrix_raw = vec![false; no_its * no_its];
@@ -47,13 +45,13 @@ pub fn dot(matches: &clap::ArgMatches) -> Result<(), Box<dyn std::error::Error +
                 matrix[row][column] = match_case(k1, k2, nmatch)
             }
         }
-        eprintln!("Matrix for {} made.", record.id());
+        eprintln!("Matrix for {} made.", record.name);
 
-        matplot(matrix, &dir, record.id())?;
+        matplot(matrix, &dir, &record.name)?;
 
         match dir.as_str() {
-            "." => eprintln!("Plot for {} made in current directory", record.id()),
-            d => eprintln!("Plot for {} made in {}", record.id(), d),
+            "." => eprintln!("Plot for {} made in current directory", record.name),
+            d => eprintln!("Plot for {} made in {}", record.name, d),
         }
     }
     Ok(())
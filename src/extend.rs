@@ -0,0 +1,127 @@
+use bio::io::fasta::IndexedReader;
+use clap::value_t;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+use crate::utils::locus::{locus_regex, Locus, Strand};
+use crate::utils::revcomp::reverse_complement;
+
+// go back to the genome for more flank, when the conserved blocks of
+// an alignment run up to its edges. sequences come out unaligned,
+// in the orientation of the alignment, ready for realignment.
+
+/// The locus extended by `left` and `right` bases either side of the
+/// sequence as it is in the alignment (so `left` is the high end of the
+/// genome on the minus strand), stopping at the ends of the contig.
+pub fn extend_locus(locus: &Locus, left: usize, right: usize, contig_length: usize) -> Locus {
+    let (low, high) = match locus.strand {
+        Strand::Plus => (left, right),
+        Strand::Minus => (right, left),
+    };
+    Locus {
+        contig: locus.contig.clone(),
+        start: locus.start.saturating_sub(low).max(1),
+        end: (locus.end + high).min(contig_length),
+        strand: locus.strand,
+    }
+}
+
+/// Open a genome fasta indexed with samtools faidx, exiting if it or
+/// the index is missing. Also returns the length of each contig.
+pub fn open_genome(genome: &str) -> (IndexedReader<File>, HashMap<String, usize>) {
+    let reader = IndexedReader::from_file(&genome).unwrap_or_else(|e| {
+        eprintln!(
+            "[-]\tCould not open the genome, or its index ({}.fai): {}",
+            genome, e
+        );
+        std::process::exit(1);
+    });
+    let contig_lengths = reader
+        .index
+        .sequences()
+        .into_iter()
+        .map(|s| (s.name, s.len as usize))
+        .collect();
+    (reader, contig_lengths)
+}
+
+/// The sequence of a locus, reverse complemented if on the minus strand.
+pub fn fetch_locus(reader: &mut IndexedReader<File>, locus: &Locus) -> io::Result<Vec<u8>> {
+    let mut seq = Vec::new();
+    // the index is zero based and half open.
+    reader.fetch(&locus.contig, locus.start as u64 - 1, locus.end as u64)?;
+    reader.read(&mut seq)?;
+    Ok(match locus.strand {
+        Strand::Plus => seq,
+        Strand::Minus => reverse_complement(&seq),
+    })
+}
+
+pub fn extend(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let genome = matches.value_of("genome").unwrap();
+    let bases = value_t!(matches.value_of("bases"), usize).unwrap_or_else(|e| e.exit());
+    // either side can be extended by a different amount.
+    let left = match matches.value_of("left") {
+        Some(_) => value_t!(matches.value_of("left"), usize).unwrap_or_else(|e| e.exit()),
+        None => bases,
+    };
+    let right = match matches.value_of("right") {
+        Some(_) => value_t!(matches.value_of("right"), usize).unwrap_or_else(|e| e.exit()),
+        None => bases,
+    };
+
+    let mut alignment = read_alignment_as(fasta, format_from_matches(matches));
+    if let Some(regex) = matches.value_of("locus_regex") {
+        alignment.set_loci(&locus_regex(regex));
+    }
+
+    let (mut reader, contig_lengths) = open_genome(genome);
+
+    let mut extended = 0;
+    for record in &alignment.matrix {
+        let locus = match &record.locus {
+            Some(l) => l,
+            None => {
+                eprintln!(
+                    "[-]\tNo coordinates in the header {}. Skipping.",
+                    record.name
+                );
+                continue;
+            }
+        };
+        let contig_length = match contig_lengths.get(&locus.contig) {
+            Some(l) => *l,
+            None => {
+                eprintln!(
+                    "[-]\tContig {} of {} is not in the genome. Skipping.",
+                    locus.contig, record.name
+                );
+                continue;
+            }
+        };
+        if locus.end > contig_length {
+            eprintln!(
+                "[-]\t{} runs past the end of contig {} ({} bp). Skipping.",
+                record.name, locus.contig, contig_length
+            );
+            continue;
+        }
+
+        let new_locus = extend_locus(locus, left, right, contig_length);
+        let seq =
+            fetch_locus(&mut reader, &new_locus).expect("[-]\tCould not read from the genome.");
+        println!(">{}\n{}", new_locus, String::from_utf8_lossy(&seq));
+        extended += 1;
+    }
+
+    eprintln!(
+        "[+]\t{} of {} sequences extended by up to {} bp on the left and {} bp on the right.",
+        extended,
+        alignment.matrix.len(),
+        left,
+        right
+    );
+}
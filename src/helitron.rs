@@ -0,0 +1,402 @@
+use clap::value_t;
+
+use crate::con::{trimmed_consensus_columns, ConsensusParams};
+use crate::utils::alignment::Alignment;
+use crate::utils::flanks::{boundary_tsds, element_flanks};
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+use crate::utils::seqcount::most_common;
+
+// rolling circle elements (Helitrons) have no terminal repeats, so look
+// for their other signatures: a 5' TC, a 3' CTRR just after a GC rich
+// hairpin, no TSD, and insertion between an A and a T in the host.
+
+/// Parameters for the Helitron search.
+#[derive(Debug, Clone, Copy)]
+pub struct HelitronParams {
+    /// how far (bp) into the consensus the termini may sit, as the
+    /// trimmed consensus keeps a little flank.
+    pub slop: usize,
+    /// how far (bp) upstream of the CTRR the hairpin may start.
+    pub distance: usize,
+    pub min_stem: usize,
+    pub max_loop: usize,
+    /// minimum GC proportion of the hairpin stem.
+    pub min_gc: f64,
+}
+
+impl Default for HelitronParams {
+    fn default() -> Self {
+        HelitronParams {
+            slop: 20,
+            distance: 40,
+            min_stem: 5,
+            max_loop: 12,
+            min_gc: 0.6,
+        }
+    }
+}
+
+impl HelitronParams {
+    /// Read the parameters from the command line. `prefix` is prepended
+    /// to each argument name, e.g. "hel_" in `reputils html`.
+    pub fn from_matches(matches: &clap::ArgMatches, prefix: &str) -> Self {
+        let arg = |name: &str| format!("{}{}", prefix, name);
+
+        let params = HelitronParams {
+            slop: value_t!(matches.value_of(&arg("slop")), usize).unwrap_or_else(|e| e.exit()),
+            distance: value_t!(matches.value_of(&arg("distance")), usize)
+                .unwrap_or_else(|e| e.exit()),
+            min_stem: value_t!(matches.value_of(&arg("min_stem")), usize)
+                .unwrap_or_else(|e| e.exit()),
+            max_loop: value_t!(matches.value_of(&arg("max_loop")), usize)
+                .unwrap_or_else(|e| e.exit()),
+            min_gc: value_t!(matches.value_of(&arg("min_gc")), f64).unwrap_or_else(|e| e.exit()),
+        };
+        // a stem of nothing has no GC content to rank it by.
+        if params.min_stem < 1 {
+            eprintln!("[-]\t--{} must be at least 1.", arg("min_stem"));
+            std::process::exit(1);
+        }
+        params
+    }
+}
+
+/// A stem loop. Coordinates are zero based, half open, and in the
+/// gap stripped consensus.
+#[derive(Debug, Clone)]
+pub struct Hairpin {
+    pub start: usize,
+    pub end: usize,
+    pub stem: usize,
+    pub loop_length: usize,
+    pub gc: f64,
+    /// bases between the end of the hairpin and the 3' terminus.
+    pub distance: usize,
+}
+
+impl Hairpin {
+    /// The hairpin as stem(loop)stem.
+    pub fn pretty(&self, consensus: &[u8]) -> String {
+        let seq = String::from_utf8_lossy(&consensus[self.start..self.end]);
+        let (left, rest) = seq.split_at(self.stem);
+        let (lp, right) = rest.split_at(self.loop_length);
+        format!("{}({}){}", left, lp, right)
+    }
+}
+
+/// The result of searching a consensus for Helitron signatures.
+#[derive(Debug, Clone)]
+pub struct Helitron {
+    /// element boundaries in the gap stripped consensus.
+    pub start: usize,
+    pub end: usize,
+    pub five_tc: bool,
+    pub three_ctrr: bool,
+    /// the last four bases of the element.
+    pub three_terminus: String,
+    pub hairpin: Option<Hairpin>,
+    /// the most common TSD at the element boundaries, confirmed by
+    /// the k-mers shared by the ends of each sequence, and how many.
+    pub tsd: Option<(String, usize)>,
+    /// sequences inserted between an A and a T.
+    pub at_sites: usize,
+    pub sequences: usize,
+    pub pass: bool,
+}
+
+impl Helitron {
+    /// A TSD in at least half of the sequences.
+    pub fn has_tsd(&self) -> bool {
+        self.tsd
+            .as_ref()
+            .is_some_and(|(_, count)| count * 2 >= self.sequences)
+    }
+}
+
+fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'T' => b'A',
+        b'G' => b'C',
+        b'C' => b'G',
+        _ => b'N',
+    }
+}
+
+/// The best hairpin (longest stem, then most GC rich) starting within
+/// `distance` bases upstream of `end`, which must end at or before it.
+/// Stems are perfect Watson-Crick pairs.
+pub fn find_hairpin(seq: &[u8], end: usize, params: &HelitronParams) -> Option<Hairpin> {
+    const MIN_LOOP: usize = 3;
+    let from = end.saturating_sub(params.distance);
+    let mut best: Option<Hairpin> = None;
+
+    for a in from..end {
+        for b in (a + 2 * params.min_stem + MIN_LOOP)..=end {
+            // pair up bases from the outside in.
+            let mut stem = 0;
+            while a + stem + MIN_LOOP < b - stem - 1
+                && complement(seq[a + stem]) != b'N'
+                && complement(seq[a + stem]) == seq[b - stem - 1]
+            {
+                stem += 1;
+            }
+            let loop_length = (b - stem) - (a + stem);
+            if stem < params.min_stem || loop_length > params.max_loop {
+                continue;
+            }
+            let gc = seq[a..a + stem]
+                .iter()
+                .filter(|&&c| c == b'G' || c == b'C')
+                .count() as f64
+                / stem as f64;
+            if gc < params.min_gc {
+                continue;
+            }
+            let better = best
+                .as_ref()
+                .map_or(true, |h| stem > h.stem || (stem == h.stem && gc > h.gc));
+            if better {
+                best = Some(Hairpin {
+                    start: a,
+                    end: b,
+                    stem,
+                    loop_length,
+                    gc,
+                    distance: end - b,
+                });
+            }
+        }
+    }
+    best
+}
+
+/// Search the consensus for a Helitron. `columns` maps the consensus
+/// to the alignment (see `trimmed_consensus_columns`), so the insertion
+/// sites and TSD's can be looked for in each sequence. TSD's are looked for
+/// at the element boundaries, and only count if `Alignment::to_tsd_hash`
+/// also finds them at the ends of that sequence.
+pub fn find_helitron(
+    alignment: &Alignment,
+    consensus: &[u8],
+    columns: &[usize],
+    params: &HelitronParams,
+    tsd: (usize, usize, usize),
+) -> Helitron {
+    let seq = consensus.to_ascii_uppercase();
+    let len = seq.len();
+    let (tsd_len, tsd_min_window, tsd_max_window) = tsd;
+
+    // 5' TC nearest the start.
+    let tc = (0..=params.slop).find(|&i| seq.get(i..i + 2) == Some(b"TC"));
+
+    // 3' CTRR ends near the end, each with its best hairpin.
+    let ctrr = |e: usize| {
+        e >= 4
+            && &seq[e - 4..e - 2] == b"CT"
+            && matches!(seq[e - 2], b'A' | b'G')
+            && matches!(seq[e - 1], b'A' | b'G')
+    };
+    let ends: Vec<(usize, Option<Hairpin>)> = (len.saturating_sub(params.slop)..=len)
+        .rev()
+        .filter(|&e| ctrr(e))
+        .map(|e| (e, find_hairpin(&seq, e - 4, params)))
+        .collect();
+    // the CTRR with the best hairpin, or the one nearest the end.
+    // reversed, as max_by keeps the last of equals.
+    let three = ends
+        .iter()
+        .rev()
+        .filter(|(_, h)| h.is_some())
+        .max_by(|(_, a), (_, b)| {
+            let (a, b) = (a.as_ref().unwrap(), b.as_ref().unwrap());
+            a.stem.cmp(&b.stem).then(a.gc.total_cmp(&b.gc))
+        })
+        .or(ends.first())
+        .cloned();
+
+    let start = tc.unwrap_or(0);
+    let (end, hairpin) = match &three {
+        Some((e, h)) => (*e, h.clone()),
+        None => (len, find_hairpin(&seq, len, params)),
+    };
+
+    // insertion sites and TSD's in each sequence.
+    let element = (start, end.max(start + 1));
+    let mut at_sites = 0;
+    for record in &alignment.matrix {
+        let (left, right) = element_flanks(&record.sequence, columns, element);
+        if left.last() == Some(&b'A') && right.first() == Some(&b'T') {
+            at_sites += 1;
+        }
+    }
+
+    let shared = alignment
+        .to_tsd_hash(tsd_len, tsd_min_window, tsd_max_window)
+        .merge(true)
+        .unwrap();
+    // no TIR to swallow a TSD, and TCTA...CTAG termini would look like one.
+    let tsds = boundary_tsds(
+        alignment,
+        columns,
+        element,
+        tsd_min_window,
+        tsd_max_window,
+        0,
+    );
+    let confirmed = tsds
+        .into_iter()
+        .zip(&alignment.matrix)
+        .filter_map(|(tsd, record)| {
+            let tsd = tsd?;
+            let kmers = shared.get(&record.name)?;
+            kmers
+                .iter()
+                .any(|k| k.to_ascii_uppercase().contains(&tsd))
+                .then_some(tsd)
+        });
+    let tsd = most_common(confirmed);
+
+    let mut helitron = Helitron {
+        start,
+        end,
+        five_tc: tc.is_some(),
+        three_ctrr: three.is_some(),
+        three_terminus: String::from_utf8_lossy(&seq[end.saturating_sub(4)..end]).to_string(),
+        hairpin,
+        tsd,
+        at_sites,
+        sequences: alignment.matrix.len(),
+        pass: false,
+    };
+    helitron.pass = helitron.five_tc
+        && helitron.three_ctrr
+        && helitron.hairpin.is_some()
+        && !helitron.has_tsd();
+    helitron
+}
+
+pub fn helitron(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let tsd_len = value_t!(matches.value_of("tsd_len"), usize).unwrap_or_else(|e| e.exit());
+    let tsd_min_window =
+        value_t!(matches.value_of("tsd_min_window"), usize).unwrap_or_else(|e| e.exit());
+    let tsd_max_window =
+        value_t!(matches.value_of("tsd_max_window"), usize).unwrap_or_else(|e| e.exit());
+    let params = HelitronParams::from_matches(matches, "");
+    let consensus_params = ConsensusParams::from_matches(matches, "");
+
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let (consensus, columns) = trimmed_consensus_columns(&alignment, &consensus_params);
+    let helitron = find_helitron(
+        &alignment,
+        &consensus,
+        &columns,
+        &params,
+        (tsd_len, tsd_min_window, tsd_max_window),
+    );
+
+    println!("Start\tEnd\t5' TC\t3' terminus\tHairpin\tStem\tLoop\tStem GC\tDistance\tTSD\tA/T sites\tHelitron");
+    let (pretty, stem, loop_length, gc, distance) = match &helitron.hairpin {
+        Some(h) => (
+            h.pretty(&consensus.to_ascii_uppercase()),
+            h.stem.to_string(),
+            h.loop_length.to_string(),
+            format!("{:.2}", h.gc),
+            h.distance.to_string(),
+        ),
+        None => (
+            "-".to_string(),
+            "-".into(),
+            "-".into(),
+            "-".into(),
+            "-".into(),
+        ),
+    };
+    println!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}/{}\t{}",
+        helitron.start + 1, // add one due to zero indexing.
+        helitron.end,
+        helitron.five_tc,
+        helitron.three_terminus,
+        pretty,
+        stem,
+        loop_length,
+        gc,
+        distance,
+        match &helitron.tsd {
+            Some((tsd, count)) => format!("{} ({}/{})", tsd, count, helitron.sequences),
+            None => "-".to_string(),
+        },
+        helitron.at_sites,
+        helitron.sequences,
+        if helitron.pass { "PASS" } else { "FAIL" }
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::alignment::Sequence;
+
+    // TC, an AT rich body, a GC rich hairpin, then CTAG.
+    const ELEMENT: &[u8] = b"TCATATATATATATATATGGCGCTTTTGCGCCTTCTAG";
+
+    #[test]
+    fn finds_the_hairpin() {
+        let hairpin = find_hairpin(ELEMENT, 34, &HelitronParams::default()).unwrap();
+        assert_eq!((hairpin.start, hairpin.end), (18, 32));
+        assert_eq!((hairpin.stem, hairpin.loop_length), (5, 4));
+        assert_eq!((hairpin.gc, hairpin.distance), (1.0, 2));
+        assert_eq!(hairpin.pretty(ELEMENT), "GGCGC(TTTT)GCGCC");
+    }
+
+    #[test]
+    fn stem_and_loop_limits() {
+        let params = HelitronParams::default();
+        // a stem of four.
+        assert!(find_hairpin(b"TGGCGTTTTCGCCT", 14, &params).is_none());
+        // a loop of thirteen.
+        assert!(find_hairpin(b"TGGCGCTTTTTTTTTTTTTGCGCCT", 25, &params).is_none());
+        assert!(find_hairpin(b"TGGCGCTTTTTTTTTTTTGCGCCT", 24, &params).is_some());
+    }
+
+    #[test]
+    fn ranks_by_gc_content() {
+        // GAGCA is 0.6 GC, GGCGC all GC.
+        let seq = b"GAGCATTTTTGCTCTTGGCGCTTTTGCGCCTT";
+        let hairpin = find_hairpin(seq, seq.len(), &HelitronParams::default()).unwrap();
+        assert_eq!((hairpin.start, hairpin.gc), (16, 1.0));
+    }
+
+    #[test]
+    fn ambiguous_bases_do_not_pair() {
+        let seq = b"T?GGCGCTTTTGCGCCNT";
+        let hairpin = find_hairpin(seq, seq.len(), &HelitronParams::default()).unwrap();
+        assert_eq!((hairpin.start, hairpin.stem), (2, 5));
+    }
+
+    #[test]
+    fn finds_a_helitron() {
+        let (left, right) = (b"CCCCCCCCCCCCA", b"TGGGGGGGGGGG");
+        let sequence = [&left[..], ELEMENT, &right[..]].concat();
+        let mut alignment = Alignment::new();
+        for i in 0..3 {
+            alignment.add_sequence(Sequence {
+                name: i.to_string(),
+                sequence: sequence.clone(),
+                locus: None,
+            });
+        }
+        let columns: Vec<usize> = (left.len()..left.len() + ELEMENT.len()).collect();
+        let params = HelitronParams::default();
+        let helitron = find_helitron(&alignment, ELEMENT, &columns, &params, (10, 2, 12));
+        assert_eq!((helitron.start, helitron.end), (0, ELEMENT.len()));
+        assert!(helitron.five_tc && helitron.three_ctrr);
+        assert_eq!(helitron.three_terminus, "CTAG");
+        assert_eq!(helitron.hairpin.unwrap().start, 18);
+        assert_eq!(helitron.at_sites, 3);
+        assert!(helitron.tsd.is_none());
+        assert!(helitron.pass);
+    }
+}
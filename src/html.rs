@@ -1,90 +1,205 @@
 // module for making an html document
 
 use bio::alignment::pairwise::*;
-use bio::io::fasta;
 use clap::value_t;
 use itertools::izip;
+use std::collections::HashMap;
 
-use crate::con::get_consensus;
+use crate::con::{get_consensus, ConsensusParams};
+use crate::divergence::copy_divergence;
 use crate::dot::match_case;
-use crate::utils::alignment::{Alignment, Sequence};
+use crate::helitron::{find_helitron, HelitronParams};
+use crate::orf::find_orfs;
+use crate::tail::{alignment_tails, AlignedTail, TailParams};
+use crate::tir::{find_tir, Tir};
+use crate::trunc::{copy_extents, histogram, summarise_truncation};
+use crate::utils::alignment::Alignment;
+use crate::utils::formats::{format_from_matches, read_alignment_as, Format};
+use crate::utils::profile::{DiversityWindow, Profile};
 use crate::utils::revcomp::reverse_complement;
 use crate::utils::windows::SeqWindows;
 
+/// Parameters for the trim, consensus, TIR, TSD and diversity steps,
+/// which `reputils batch` runs over each family too.
+#[derive(Debug, Clone, Copy)]
+pub struct Pipeline {
+    /// `None` to detect the format of each alignment.
+    pub input_format: Option<Format>,
+    pub trim_extend: usize,
+    pub trim_next_hit: usize,
+    pub trim_miss: f64,
+    pub trim_iden: f64,
+    pub consensus: ConsensusParams,
+    pub tir_window: usize,
+    pub tir_min_len: usize,
+    pub tir_min_iden: f64,
+    pub tsd_len: usize,
+    pub tsd_min_window: usize,
+    pub tsd_max_window: usize,
+    pub div_window_size: usize,
+    pub div_window_step: usize,
+    pub div_min_sites: f32,
+}
+
+/// The output of each step of the `Pipeline`.
+pub struct Stages {
+    pub trimmed: Alignment,
+    /// the columns of the alignment kept by the trim.
+    pub trim_start: usize,
+    pub trim_end: usize,
+    /// the consensus of the trimmed alignment, gaps and all.
+    pub aligned_consensus: Vec<u8>,
+    pub consensus: Vec<u8>,
+    /// the alignment column of each base of `consensus`.
+    pub columns: Vec<usize>,
+    pub tir: Tir,
+    pub tsds: HashMap<String, Vec<String>>,
+    /// of the whole alignment, not the trimmed one.
+    pub profile: Profile,
+    pub div: Vec<DiversityWindow>,
+}
+
+impl Pipeline {
+    /// Take the options shared by `html` and `batch`.
+    pub fn from_matches(matches: &clap::ArgMatches) -> Self {
+        Pipeline {
+            input_format: format_from_matches(matches),
+            trim_extend: value_t!(matches.value_of("trim_extend"), usize)
+                .unwrap_or_else(|e| e.exit()),
+            trim_next_hit: value_t!(matches.value_of("trim_next_hit"), usize)
+                .unwrap_or_else(|e| e.exit()),
+            trim_miss: value_t!(matches.value_of("trim_miss"), f64).unwrap_or_else(|e| e.exit()),
+            trim_iden: value_t!(matches.value_of("trim_iden"), f64).unwrap_or_else(|e| e.exit()),
+            consensus: ConsensusParams::from_matches(matches, "con_"),
+            tir_window: value_t!(matches.value_of("tir_window"), usize)
+                .unwrap_or_else(|e| e.exit()),
+            tir_min_len: value_t!(matches.value_of("tir_min_len"), usize)
+                .unwrap_or_else(|e| e.exit()),
+            tir_min_iden: value_t!(matches.value_of("tir_min_iden"), f64)
+                .unwrap_or_else(|e| e.exit()),
+            tsd_len: value_t!(matches.value_of("tsd_len"), usize).unwrap_or_else(|e| e.exit()),
+            tsd_min_window: value_t!(matches.value_of("tsd_min_window"), usize)
+                .unwrap_or_else(|e| e.exit()),
+            tsd_max_window: value_t!(matches.value_of("tsd_max_window"), usize)
+                .unwrap_or_else(|e| e.exit()),
+            div_window_size: value_t!(matches.value_of("div_window_size"), usize)
+                .unwrap_or_else(|e| e.exit()),
+            div_window_step: value_t!(matches.value_of("div_window_step"), usize)
+                .unwrap_or_else(|e| e.exit()),
+            div_min_sites: value_t!(matches.value_of("div_min_sites"), f32)
+                .unwrap_or_else(|e| e.exit()),
+        }
+    }
+
+    /// Trim the alignment, call the consensus of what is left, and
+    /// look for a TIR in it. TSD's and diversity are taken from the
+    /// whole alignment.
+    pub fn run(&self, alignment: &Alignment) -> Stages {
+        let read_number = alignment.matrix.len() as i32;
+        let profile = alignment.get_profile();
+
+        // trim
+        let blocks = profile.find_blocks(self.trim_miss, self.trim_iden);
+        let (trim_start, trim_end) = blocks.trim_bounds(
+            alignment.matrix[0].len(),
+            self.trim_extend,
+            self.trim_next_hit,
+        );
+        let trimmed = blocks
+            .trim(
+                alignment.clone(),
+                self.trim_extend,
+                self.trim_next_hit,
+                true,
+            )
+            .unwrap_or_default();
+
+        // consensus, and the alignment column of each base once the gaps go.
+        let aligned_consensus = get_consensus(&trimmed.get_profile(), read_number, &self.consensus);
+        let (columns, consensus): (Vec<usize>, Vec<u8>) = aligned_consensus
+            .iter()
+            .enumerate()
+            .filter(|(_, &e)| e != 45)
+            .map(|(i, &e)| (trim_start + i, e))
+            .unzip();
+
+        let tir = find_tir(
+            &consensus,
+            self.tir_window,
+            self.tir_min_len,
+            self.tir_min_iden,
+        );
+
+        let tsds = alignment
+            .to_tsd_hash(self.tsd_len, self.tsd_min_window, self.tsd_max_window)
+            .merge(true)
+            .unwrap();
+
+        let div = profile.div_windows(
+            self.div_window_size,
+            self.div_window_step,
+            self.div_min_sites,
+        );
+
+        Stages {
+            trimmed,
+            trim_start,
+            trim_end,
+            aligned_consensus,
+            consensus,
+            columns,
+            tir,
+            tsds,
+            profile,
+            div,
+        }
+    }
+}
+
 pub fn render_html(matches: &clap::ArgMatches) {
     // parse command line args
     let fasta = matches.value_of("fasta").unwrap();
-    let trim_extend = value_t!(matches.value_of("trim_extend"), usize).unwrap_or_else(|e| e.exit());
-    let trim_next_hit =
-        value_t!(matches.value_of("trim_next_hit"), usize).unwrap_or_else(|e| e.exit());
-    let trim_miss = value_t!(matches.value_of("trim_miss"), f64).unwrap_or_else(|e| e.exit());
-    let trim_iden = value_t!(matches.value_of("trim_iden"), f64).unwrap_or_else(|e| e.exit());
+    let pipeline = Pipeline::from_matches(matches);
+    let helitron_params = HelitronParams::from_matches(matches, "hel_");
+    let tail_params = TailParams::from_matches(matches, "tail_");
     let dot_wsize = value_t!(matches.value_of("dot_wsize"), usize).unwrap_or_else(|e| e.exit());
     let dot_wstep = value_t!(matches.value_of("dot_wstep"), usize).unwrap_or_else(|e| e.exit());
     let dot_nmatch = value_t!(matches.value_of("dot_nmatch"), usize).unwrap_or_else(|e| e.exit());
-    let tsd_len = value_t!(matches.value_of("tsd_len"), usize).unwrap_or_else(|e| e.exit());
-    let tsd_min_window =
-        value_t!(matches.value_of("tsd_min_window"), usize).unwrap_or_else(|e| e.exit());
-    let tsd_max_window =
-        value_t!(matches.value_of("tsd_max_window"), usize).unwrap_or_else(|e| e.exit());
-    let div_window_size =
-        value_t!(matches.value_of("div_window_size"), usize).unwrap_or_else(|e| e.exit());
-    let div_window_step =
-        value_t!(matches.value_of("div_window_step"), usize).unwrap_or_else(|e| e.exit());
-
-    let reader = fasta::Reader::from_file(fasta).expect("[-]\tPath invalid.");
-    let mut read_number = 0i32;
-
-    // read the fasta into Alignment
-    // we need to do this three times
-    // and make three copies of the alignment
-    // large alignments will require large amounts of memory...
-    let mut matrix = Alignment::new();
-    let mut tsd_matrix = Alignment::new();
-    let mut div_window_matrix = Alignment::new();
-
-    for record in reader.records() {
-        let fasta_record = record.expect("[-]\tError during fasta record parsing.");
-        matrix.add_sequence(Sequence {
-            name: fasta_record.id().to_string(),
-            sequence: fasta_record.seq().to_vec(),
-        });
-        tsd_matrix.add_sequence(Sequence {
-            name: fasta_record.id().to_string(),
-            sequence: fasta_record.seq().to_vec(),
-        });
-        div_window_matrix.add_sequence(Sequence {
-            name: fasta_record.id().to_string(),
-            sequence: fasta_record.seq().to_vec(),
-        });
-        read_number += 1;
-    }
-    eprintln!("[+]\tAlignments in memory.");
+    let trunc_min_occupancy =
+        value_t!(matches.value_of("trunc_min_occupancy"), f64).unwrap_or_else(|e| e.exit());
+    let trunc_slop = value_t!(matches.value_of("trunc_slop"), usize).unwrap_or_else(|e| e.exit());
+    let trunc_bin = value_t!(matches.value_of("trunc_bin"), usize).unwrap_or_else(|e| e.exit());
+    let divergence_cpg = matches.is_present("divergence_cpg");
+    let orf_min_len = value_t!(matches.value_of("orf_min_len"), usize).unwrap_or_else(|e| e.exit());
 
-    // find the blocks and trim
-    let blocks = matrix.find_blocks(trim_miss, trim_iden);
-    let trimmed = blocks.trim(matrix, trim_extend, trim_next_hit, true);
-
-    // perhaps do something more sensible with this.
-    let ok_trimmed = match trimmed {
-        Some(t) => t,
-        None => Alignment::new(),
-    };
-    eprintln!("[+]\tTrimmed alignment.");
+    // read the alignment into Alignment
+    let matrix = read_alignment_as(fasta, pipeline.input_format);
+    let read_number = matrix.matrix.len() as i32;
+    eprintln!("[+]\tAlignments in memory.");
 
     // for the header of the html
     let mut seq_names = String::new();
     seq_names += fasta;
 
     //
-    // Making consensus:
-    // Get the profile of an Alignment, then call get_consensus
-    // Delete gaps.
+    // Trim, consensus, TIR, TSD's and diversity windows,
+    // the same steps `reputils batch` runs on each family.
     //
-    let profile = ok_trimmed.get_profile();
-    let mut consensus = get_consensus(profile, read_number);
-    consensus.retain(|&e| e != 45);
-    eprintln!("[+]\tConsensus sequence generated.");
+    let Stages {
+        trimmed: ok_trimmed,
+        trim_end,
+        aligned_consensus,
+        consensus,
+        columns,
+        tir,
+        tsds: tsd,
+        profile: div_window_profile,
+        div: div_window_data,
+        ..
+    } = pipeline.run(&matrix);
+    // divergence of each copy from the consensus, before the gaps go.
+    let divergences = copy_divergence(&ok_trimmed, &aligned_consensus, divergence_cpg);
+    eprintln!("[+]\tTrimmed alignment and generated consensus.");
 
     //
     // Make the dotplot:
@@ -151,11 +266,42 @@ pub fn render_html(matches: &clap::ArgMatches) {
     eprintln!("[+]\tSVG dotplot made.");
 
     //
-    // Self align the consensus:
+    // TIR from self aligning the consensus:
     // Consensus vs revcomp consensus
     // Print to an html table
     //
-    eprintln!("[+]\tSelf aligning for TIR identification.");
+    let tir_table = format!(
+        r###"<table class="tsds">
+                <tr>
+                    <th>Left arm</th>
+                    <th>Right arm</th>
+                    <th>Length</th>
+                    <th>Identity</th>
+                    <th>Mismatches</th>
+                    <th>Indels</th>
+                    <th>TIR</th>
+                </tr>
+                <tr>
+                    <td>{}-{}</td>
+                    <td>{}-{}</td>
+                    <td>{}</td>
+                    <td>{:.3}</td>
+                    <td>{}</td>
+                    <td>{}</td>
+                    <td>{}</td>
+                </tr>
+                </table>"###,
+        tir.left_start + 1,
+        tir.left_end,
+        tir.right_start + 1,
+        tir.right_end,
+        tir.length,
+        tir.identity,
+        tir.mismatches,
+        tir.indels,
+        if tir.pass { "PASS" } else { "FAIL" }
+    );
+
     let reverse_consensus = reverse_complement(&consensus);
 
     let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
@@ -238,16 +384,141 @@ pub fn render_html(matches: &clap::ArgMatches) {
 
     let consensus_formatted = std::str::from_utf8(&consensus).unwrap().to_string();
 
+    //
+    // Helitron signatures:
+    // 5' TC, 3' CTRR after a hairpin, no TSD, A/T insertion sites.
+    //
+    eprintln!("[+]\tLooking for Helitron signatures.");
+    let helitron = find_helitron(
+        &matrix,
+        &consensus,
+        &columns,
+        &helitron_params,
+        (
+            pipeline.tsd_len,
+            pipeline.tsd_min_window,
+            pipeline.tsd_max_window,
+        ),
+    );
+    let helitron_hairpin = match &helitron.hairpin {
+        Some(h) => format!(
+            "{} (stem {} bp, loop {} bp, stem GC {:.2}, {} bp from the 3' terminus)",
+            h.pretty(&consensus.to_ascii_uppercase()),
+            h.stem,
+            h.loop_length,
+            h.gc,
+            h.distance
+        ),
+        None => "<i>None detected</i>".to_string(),
+    };
+    let helitron_table = format!(
+        r###"<table class="tsds">
+                <tr>
+                    <th>Element</th>
+                    <th>5' TC</th>
+                    <th>3' terminus</th>
+                    <th>Hairpin</th>
+                    <th>TSD</th>
+                    <th>A/T sites</th>
+                    <th>Helitron</th>
+                </tr>
+                <tr>
+                    <td>{}-{}</td>
+                    <td>{}</td>
+                    <td>{}</td>
+                    <td>{}</td>
+                    <td>{}</td>
+                    <td>{}/{}</td>
+                    <td>{}</td>
+                </tr>
+                </table>"###,
+        helitron.start + 1,
+        helitron.end,
+        if helitron.five_tc { "Yes" } else { "No" },
+        helitron.three_terminus,
+        helitron_hairpin,
+        match &helitron.tsd {
+            Some((tsd, count)) => format!("{} ({}/{})", tsd, count, helitron.sequences),
+            None => "<i>None detected</i>".to_string(),
+        },
+        helitron.at_sites,
+        helitron.sequences,
+        if helitron.pass { "PASS" } else { "FAIL" }
+    );
+
+    //
+    // ORF map:
+    // ORF's in the six frames of the consensus, one track per frame.
+    // SVG scaled to the length of the consensus, plus a table of coordinates.
+    //
+    eprintln!("[+]\tFinding ORF's.");
+    let orfs = find_orfs(&consensus, orf_min_len, false);
+
+    let orf_track_height = 40;
+    let orf_svg_height = orf_track_height * 6;
+    let mut orf_plot = format!(
+        r###"
+                <svg viewBox="0 0 {} {}" width=90% preserveAspectRatio="none"
+                    id="svg_orfplot"
+                    xmlns="http://www.w3.org/2000/svg" >
+                <rect width="100%" height="100%" fill="#F5F5DC" />
+            "###,
+        consensus.len(),
+        orf_svg_height
+    );
+    // +1, +2, +3 on top, then -1, -2, -3.
+    for orf in &orfs {
+        let track = match orf.strand {
+            '+' => orf.frame - 1,
+            _ => orf.frame + 2,
+        };
+        orf_plot += &format!(
+            r###"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" />"###,
+            orf.start,
+            track * orf_track_height + orf_track_height / 4,
+            orf.end - orf.start,
+            orf_track_height / 2,
+            if orf.strand == '+' {
+                "#1F77B4"
+            } else {
+                "#D62728"
+            }
+        );
+    }
+    orf_plot += "</svg>";
+
+    let mut orf_table = String::new();
+    for (i, orf) in orfs.iter().enumerate() {
+        orf_table += &format!(
+            "<tr><td>ORF{}</td><td>{}{}</td><td>{}-{}</td><td>{}</td></tr>",
+            i + 1,
+            orf.strand,
+            orf.frame,
+            orf.start + 1,
+            orf.end,
+            orf.len()
+        );
+    }
+    let orf_map = format!(
+        r###"<p>Open reading frames of at least {} amino acids, from stop codon to stop codon. Frames +1 to +3 (blue) are the top three tracks, -1 to -3 (red) the bottom three.</p>
+            {}
+            <table class="tsds">
+                <tr>
+                    <th>ORF</th>
+                    <th>Frame</th>
+                    <th>Position</th>
+                    <th>Length (aa)</th>
+                </tr>
+                {}
+                </table>"###,
+        orf_min_len, orf_plot, orf_table
+    );
+
     //
     // Target Site Duplication identification
     // Not entirely sure about this code, but it may help...
     // write a table of potential TSD's to an html table
     //
-    let tsd = tsd_matrix
-        .to_tsd_hash(tsd_len, tsd_min_window, tsd_max_window)
-        .merge(true)
-        .unwrap();
-
     // write this table to html
     let mut tsd_table = String::new();
     // start with <tr>'s as table can be put below
@@ -287,6 +558,191 @@ pub fn render_html(matches: &clap::ArgMatches) {
         tsd_table += "</tr>";
     }
 
+    //
+    // 3' tails:
+    // poly-A or other simple repeats at the 3' end of the consensus
+    // and each sequence, and whether trimming cut them off.
+    //
+    let (consensus_tail, member_tails) =
+        alignment_tails(&matrix, trim_end, &pipeline.consensus, &tail_params);
+    let tail_row = |name: &str, tail: &Option<AlignedTail>| match tail {
+        Some(t) => format!(
+            "<tr><td>{}</td><td>({})n</td><td>{}</td><td>{:.2}</td><td>{}-{}</td><td>{}</td></tr>",
+            name,
+            t.tail.motif,
+            t.tail.len(),
+            t.tail.purity,
+            t.column_start + 1,
+            t.column_end,
+            t.position
+        ),
+        None => format!(
+            "<tr><td>{}</td><td><i>None detected</i></td><td></td><td></td><td></td><td></td></tr>",
+            name
+        ),
+    };
+    let mut tail_rows = tail_row("Consensus", &consensus_tail);
+    for (name, tail) in &member_tails {
+        tail_rows += &tail_row(name, tail);
+    }
+    let tail_table = format!(
+        r###"<p>{} of {} sequences have a 3' tail. The 3' trim boundary is at column {}.</p>
+            <table class="tsds">
+                <tr>
+                    <th>Sequence</th>
+                    <th>Motif</th>
+                    <th>Length</th>
+                    <th>Purity</th>
+                    <th>Columns</th>
+                    <th>Trim</th>
+                </tr>
+                {}
+                </table>"###,
+        member_tails.iter().filter(|(_, t)| t.is_some()).count(),
+        member_tails.len(),
+        trim_end,
+        tail_rows
+    );
+
+    //
+    // Truncation:
+    // where each copy starts and ends in the core, found allowing for
+    // the gaps truncated copies leave. Starts on the top track, ends below.
+    //
+    let (core_start, core_end) = matrix
+        .find_blocks_gap_aware(trunc_min_occupancy, pipeline.trim_iden)
+        .trim_bounds(matrix.matrix[0].len(), 0, pipeline.trim_next_hit);
+    let extents = copy_extents(&matrix, core_start, core_end, trunc_slop);
+    let truncation = summarise_truncation(&extents, core_start, core_end, trunc_bin);
+    let copies: Vec<_> = extents.iter().filter_map(|(_, e)| e.as_ref()).collect();
+    let starts = histogram(
+        copies.iter().map(|e| e.start),
+        core_start,
+        core_end,
+        trunc_bin,
+    );
+    let ends = histogram(
+        copies.iter().map(|e| e.end - 1),
+        core_start,
+        core_end,
+        trunc_bin,
+    );
+
+    let trunc_track_height = 100;
+    let trunc_y_max = starts
+        .iter()
+        .chain(&ends)
+        .max()
+        .copied()
+        .unwrap_or(0)
+        .max(1);
+    let mut trunc_plot = format!(
+        r###"
+                <svg viewBox="0 0 {} {}" width=90% preserveAspectRatio="none"
+                    id="svg_truncplot"
+                    xmlns="http://www.w3.org/2000/svg" >
+                <rect width="100%" height="100%" fill="#F5F5DC" />
+            "###,
+        starts.len() * trunc_bin,
+        trunc_track_height * 2
+    );
+    for (i, (&s, &e)) in starts.iter().zip(&ends).enumerate() {
+        let (s, e) = (
+            s * trunc_track_height / trunc_y_max,
+            e * trunc_track_height / trunc_y_max,
+        );
+        trunc_plot += &format!(
+            r###"<rect x="{x}" y="{}" width="{w}" height="{}" fill="#1F77B4" /><rect x="{x}" y="{}" width="{w}" height="{}" fill="#D62728" />"###,
+            trunc_track_height - s,
+            s,
+            trunc_track_height,
+            e,
+            x = i * trunc_bin,
+            w = trunc_bin
+        );
+    }
+    trunc_plot += "</svg>";
+
+    let truncation_summary = format!(
+        r###"<p>The core is columns {}-{}. {} full-length, {} 5' truncated, {} 3' truncated, {} internal fragments and {} absent copies.{}</p>
+            <p>Copy starts (blue, top) and ends (red, bottom) along the core, in bins of {} columns. The tallest bar is {} copies.</p>
+            {}"###,
+        core_start + 1,
+        core_end,
+        truncation.full_length,
+        truncation.five_prime,
+        truncation.three_prime,
+        truncation.internal,
+        truncation.absent,
+        match truncation.modal_truncation {
+            Some((start, end, copies)) => format!(
+                " The modal 5' truncation point is core position {}-{} ({} copies).",
+                start - core_start + 1,
+                end - core_start,
+                copies
+            ),
+            None => String::new(),
+        },
+        trunc_bin,
+        trunc_y_max,
+        trunc_plot
+    );
+
+    //
+    // Divergence:
+    // a histogram of the Kimura divergence of each copy from the
+    // consensus in 1% bins, like a RepeatMasker repeat landscape.
+    //
+    let kimuras: Vec<f64> = divergences.iter().filter_map(|d| d.kimura).collect();
+    let percents: Vec<usize> = kimuras.iter().map(|k| (k * 100.0) as usize).collect();
+    let divergence_bins = histogram(
+        percents.iter().copied(),
+        0,
+        percents.iter().max().map_or(1, |m| m + 1),
+        1,
+    );
+    let divergence_height = 100;
+    let divergence_bar = 10;
+    let divergence_y_max = divergence_bins.iter().max().copied().unwrap_or(0).max(1);
+    let mut divergence_plot = format!(
+        r###"
+                <svg viewBox="0 0 {} {}" width=60% preserveAspectRatio="none"
+                    id="svg_divergenceplot"
+                    xmlns="http://www.w3.org/2000/svg" >
+                <rect width="100%" height="100%" fill="#F5F5DC" />
+            "###,
+        divergence_bins.len() * divergence_bar,
+        divergence_height
+    );
+    for (i, &count) in divergence_bins.iter().enumerate() {
+        let height = count * divergence_height / divergence_y_max;
+        divergence_plot += &format!(
+            r###"<rect x="{}" y="{}" width="{}" height="{}" fill="#1F77B4" stroke="#F5F5DC" />"###,
+            i * divergence_bar,
+            divergence_height - height,
+            divergence_bar,
+            height
+        );
+    }
+    divergence_plot += "</svg>";
+
+    let divergence_summary = format!(
+        r###"<p>Kimura 2-parameter divergence{} of each copy from the consensus of the trimmed alignment, from 0% (left) in 1% bins. The tallest bar is {} copies. The mean is {:.1}%{}.</p>
+            {}"###,
+        if divergence_cpg {
+            " (CpG adjusted)"
+        } else {
+            ""
+        },
+        divergence_y_max,
+        100.0 * kimuras.iter().sum::<f64>() / kimuras.len().max(1) as f64,
+        match divergences.len() - kimuras.len() {
+            0 => String::new(),
+            n => format!(", and {} copies are too diverged to estimate", n),
+        },
+        divergence_plot
+    );
+
     //
     // Windows of diversity across TE
     // Much functionality poached from tidk::plot (https://github.com/tolkit/telomeric-identifier/blob/main/src/plot.rs)
@@ -294,14 +750,12 @@ pub fn render_html(matches: &clap::ArgMatches) {
     //
     eprintln!("[+]\tMaking diversity windows.");
 
-    let div_window_data = div_window_matrix.div_windows(div_window_size, div_window_step, true);
-
-    // get the maximum y value
+    // get the maximum y value, of the windows with enough sites compared.
     let div_y_max = div_window_data
         .iter()
-        .map(|(_x, _y, z)| z)
-        .max_by(|x, y| x.partial_cmp(y).expect("Tried to compare a NaN"))
-        .unwrap();
+        .filter_map(|w| w.pi_per_site)
+        .fold(0.0f32, f32::max)
+        .max(f32::EPSILON);
 
     // make svg of dotplot
     let mut div_plot = String::new();
@@ -336,20 +790,25 @@ pub fn render_html(matches: &clap::ArgMatches) {
     // distance between points on the x axis
     let div_x_bin: f64 = div_width_incl_margins as f64 / div_svg_width as f64;
 
-    // move to the initial point
-    path += &format!(
-        "M{},{}",
-        MARGIN as f64 / 2f64,
-        div_height as f64
-            - MARGIN as f64 / 2.0
-            - scale_y(
-                div_window_data[0].2 as f64,
-                0.0,
-                div_height as f64 - MARGIN as f64 / 2.0,
-                0.0,
-                *div_y_max as f64
-            )
-    );
+    // move to the initial point. the pen is lifted over windows
+    // with too few sites compared.
+    let mut pen_down = false;
+    if let Some(pi) = div_window_data[0].pi_per_site {
+        path += &format!(
+            "M{},{}",
+            MARGIN as f64 / 2f64,
+            div_height as f64
+                - MARGIN as f64 / 2.0
+                - scale_y(
+                    pi as f64,
+                    0.0,
+                    div_height as f64 - MARGIN as f64 / 2.0,
+                    0.0,
+                    div_y_max as f64
+                )
+        );
+        pen_down = true;
+    }
 
     // keep track of x bins
     let mut div_bin: f64 = div_x_bin;
@@ -369,26 +828,30 @@ pub fn render_html(matches: &clap::ArgMatches) {
     // iterate over the data and populate the path element
     for (index, element) in div_window_data.iter().skip(1).enumerate() {
         // make the path element
-        path += &format!(
-            "L{},{}",
-            div_bin + (MARGIN as f64 / 2.0),
-            div_height as f64
-                - MARGIN as f64 / 2.0
-                - scale_y(
-                    element.2 as f64,
-                    0.0,
-                    div_height as f64 - MARGIN as f64 / 2.0,
-                    0.0,
-                    *div_y_max as f64
-                )
-        );
+        if let Some(pi) = element.pi_per_site {
+            path += &format!(
+                "{}{},{}",
+                if pen_down { "L" } else { "M" },
+                div_bin + (MARGIN as f64 / 2.0),
+                div_height as f64
+                    - MARGIN as f64 / 2.0
+                    - scale_y(
+                        pi as f64,
+                        0.0,
+                        div_height as f64 - MARGIN as f64 / 2.0,
+                        0.0,
+                        div_y_max as f64
+                    )
+            );
+        }
+        pen_down = element.pi_per_site.is_some();
         // create the axis text elements
         if test_vec.contains(&index) {
             div_x_axis_text += &format!(
-                r###"<text x="{x}" y="{y}" class="x_axis_text">{text}</text>"###,
+                r###"<text x="{x}" y="{y}" class="x_axis_text">{text} bp</text>"###,
                 x = div_bin + MARGIN as f64 / 2.0,
                 y = div_height - (MARGIN / 4),
-                text = format!("{} bp", element.0)
+                text = element.start
             );
         }
 
@@ -412,7 +875,7 @@ pub fn render_html(matches: &clap::ArgMatches) {
     for _ in 0..5 {
         if diversity_text != 0.0 {
             diversity_text_string += &format!(
-                r###"<text x="{}" y="{}" class="y_axis_text">{:.2}</text>"###,
+                r###"<text x="{}" y="{}" class="y_axis_text">{:.3}</text>"###,
                 0, mut_diversity_divisions, diversity_text
             );
         }
@@ -425,6 +888,56 @@ pub fn render_html(matches: &clap::ArgMatches) {
 
     div_plot += "</svg>";
 
+    //
+    // Coverage in the same windows as the diversity:
+    // the mean number of sequences which are not a gap,
+    // drawn on the same x axis so the two plots line up.
+    //
+    let coverage = div_window_profile.coverage();
+    let mut cov_plot = format!(
+        r###"
+                <svg viewBox="0 0 {} {}" width=90%
+                    id="svg_covplot"
+                    xmlns="http://www.w3.org/2000/svg" >
+                <rect width="100%" height="100%" fill="#F5F5DC" />
+            "###,
+        div_svg_width, div_height
+    );
+    let mut cov_path = String::new();
+    for (index, window) in div_window_data.iter().enumerate() {
+        let mean = coverage[window.start..window.end].iter().sum::<usize>() as f64
+            / (window.end - window.start) as f64;
+        cov_path += &format!(
+            "{}{},{}",
+            if index == 0 { "M" } else { "L" },
+            index as f64 * div_x_bin + MARGIN as f64 / 2.0,
+            div_height as f64
+                - MARGIN as f64 / 2.0
+                - scale_y(
+                    mean,
+                    0.0,
+                    div_height as f64 - MARGIN as f64 / 2.0,
+                    0.0,
+                    read_number as f64
+                )
+        );
+    }
+    cov_plot += &format!(
+        r###"<path d="{}" id="cov_path" stroke="black" fill="none" stroke-width="1" />"###,
+        cov_path
+    );
+    cov_plot += &div_x_axis_text;
+    // y axis in sequences, at the same heights as the diversity labels.
+    for i in 1..5 {
+        cov_plot += &format!(
+            r###"<text x="{}" y="{}" class="y_axis_text">{:.0}</text>"###,
+            0,
+            div_height - i * div_y_axis_divisions,
+            read_number as f64 * i as f64 / 5.0
+        );
+    }
+    cov_plot += "</svg>";
+
     //
     // The final html:
     // Add all the strings created above
@@ -432,6 +945,27 @@ pub fn render_html(matches: &clap::ArgMatches) {
     // It's not properly formatted, but it works.
     //
 
+    let terminal_inverted_repeat = format!(
+        "<p>Best local alignment of the consensus termini (searched {} bp in from either end):</p>
+            {}
+            <p>Forward consensus aligned to reverse complement of consensus:</p>
+            <p>Scroll left/right to view more of alignment.</p>
+            <table>
+            {}{}{}
+            </table>",
+        pipeline.tir_window, tir_table, fwd, matches, rev
+    );
+    let target_site_duplication_table = format!(
+        r###"<table class="tsds">
+                <tr id="first_row">
+                    <th><b>Alignment ID: <b></th>
+                    <th><b>TSD's &rarr;<b></th>
+                </tr>
+                {}
+                </table>"###,
+        tsd_table
+    );
+
     let html = format!(
         r###"
             <!DOCTYPE html>
@@ -484,14 +1018,29 @@ pub fn render_html(matches: &clap::ArgMatches) {
                     {dotplot}
                     <h3>TIR:</h3>
                     {terminal_inverted_repeat}
+                    <h3>Helitron:</h3>
+                    <p>Rolling circle signatures: a 5' TC, a 3' CTRR just after a GC rich hairpin, no TSD, and insertion between an A and a T.</p>
+                    {helitron_table}
+                    <h3>ORF map:</h3>
+                    {orf_map}
                     <h3>TSD's:</h3>
                     <p>A table of potential target site duplications. K-mers shown in the table are present at either ends of the sequence.</p>
                     {target_site_duplication_table}
+                    <h3>3' tails:</h3>
+                    <p>Poly-A or other simple sequence tails, as at the 3' end of LINE's and SINE's. Columns are in the untrimmed alignment; tails outside or spanning the trim boundary were (partly) trimmed off.</p>
+                    {tail_table}
+                    <h3>Truncation:</h3>
+                    {truncation_summary}
+                    <h3>Divergence from the consensus:</h3>
+                    {divergence_summary}
                     <h3>Nuleotide diversity in windows:</h3>
-                    <p>X-axis indicates number of base pairs into TE and the y-axis shows the nucleotide diversity (pi) at that point in the alignment.</p>
-                    <p>Note, gaps present in the alignment will be depicted here.</p>
+                    <p>X-axis indicates number of base pairs into TE and the y-axis shows the nucleotide diversity (pi) per site compared at that point in the alignment.</p>
+                    <p>Note, sites where either sequence of a pair is a gap or N are not compared, and the line is broken over windows with fewer than {diversity_min_sites} sites compared per pair.</p>
                     <p>Window size - {diversity_window_size}; step size - {diversity_step_size}</p>
                     {diversity_windows_plot}
+                    <h3>Coverage in windows:</h3>
+                    <p>The mean number of sequences which are not a gap, in the same windows as the diversity above. Jumps in coverage often mark the ends of the TE.</p>
+                    {coverage_windows_plot}
                 </body>
             </html>
             "###,
@@ -502,27 +1051,18 @@ pub fn render_html(matches: &clap::ArgMatches) {
         step_size = dot_wstep,
         mismatches = dot_nmatch,
         dotplot = dot_plot,
-        terminal_inverted_repeat = format!(
-            "<p>Forward consensus aligned to reverse complement of consensus:</p>
-                <p>Scroll left/right to view more of alignment.</p>
-                <table>
-                {}{}{}
-                </table>",
-            fwd, matches, rev
-        ),
-        target_site_duplication_table = format!(
-            r###"<table class="tsds">
-                    <tr id="first_row">
-                        <th><b>Alignment ID: <b></th>
-                        <th><b>TSD's &rarr;<b></th>
-                    </tr>
-                    {}
-                    </table>"###,
-            tsd_table
-        ),
+        terminal_inverted_repeat = terminal_inverted_repeat,
+        helitron_table = helitron_table,
+        orf_map = orf_map,
+        target_site_duplication_table = target_site_duplication_table,
+        tail_table = tail_table,
+        truncation_summary = truncation_summary,
+        divergence_summary = divergence_summary,
         diversity_windows_plot = div_plot,
-        diversity_window_size = div_window_size,
-        diversity_step_size = div_window_step,
+        coverage_windows_plot = cov_plot,
+        diversity_window_size = pipeline.div_window_size,
+        diversity_step_size = pipeline.div_window_step,
+        diversity_min_sites = pipeline.div_min_sites,
     );
     println!("{}", html);
 }
@@ -1,8 +1,22 @@
+pub mod align;
+pub mod batch;
+pub mod classify;
+pub mod collect;
 pub mod con;
+pub mod cov;
 pub mod div;
+pub mod divergence;
 pub mod dot;
+pub mod extend;
+pub mod helitron;
 pub mod html;
+pub mod ltr;
+pub mod orf;
+pub mod pep;
+pub mod stats;
+pub mod tail;
 pub mod tir;
+pub mod trunc;
 pub mod tsd;
 pub mod ttc;
 pub mod utils;
@@ -0,0 +1,206 @@
+use bio::alignment::pairwise::*;
+use clap::value_t;
+
+use crate::con::{trimmed_consensus, ConsensusParams};
+use crate::tir::count_operations;
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+
+// the forward counterpart to the TIR search in crate::tir.
+// the two ends of the consensus are aligned to each other without
+// reverse complementing, so long direct repeats (LTR's) show up as a good hit.
+
+/// The result of searching the termini of a consensus for
+/// long terminal (direct) repeats. Coordinates are zero based,
+/// half open, and in the gap stripped consensus.
+#[derive(Debug, Clone)]
+pub struct Ltr {
+    pub five_start: usize,
+    pub five_end: usize,
+    pub three_start: usize,
+    pub three_end: usize,
+    pub length: usize,
+    pub identity: f64,
+    pub mismatches: usize,
+    pub indels: usize,
+    // the first and last two bases of each LTR.
+    pub five_termini: (String, String),
+    pub three_termini: (String, String),
+    pub pass: bool,
+    // kept so the LTR's can be pretty printed.
+    pub alignment: bio::alignment::Alignment,
+    pub five_ltr: Vec<u8>,
+    pub three_ltr: Vec<u8>,
+}
+
+impl Ltr {
+    /// Pretty print the alignment of the 5' LTR against the 3' LTR.
+    pub fn pretty(&self) -> String {
+        self.alignment.pretty(&self.five_ltr, &self.three_ltr)
+    }
+
+    /// Do both LTR's start with TG and end with CA?
+    pub fn tg_ca(&self) -> bool {
+        self.five_termini.0 == "TG"
+            && self.five_termini.1 == "CA"
+            && self.three_termini.0 == "TG"
+            && self.three_termini.1 == "CA"
+    }
+}
+
+/// Locally align the first `window` bases of the consensus to the
+/// last `window` bases. The best local hit is reported as the putative
+/// pair of LTR's, and passes if it is at least `min_length` long and
+/// `min_identity` identical. Identity, mismatches and indels are from
+/// aligning the two LTR's again once their boundaries are snapped.
+pub fn find_ltr(consensus: &[u8], window: usize, min_length: usize, min_identity: f64) -> Ltr {
+    let len = consensus.len();
+    // don't let the two ends overlap on short sequences.
+    let window = window.min(len / 2);
+    let offset = len - window;
+
+    let five_window = consensus[..window].to_ascii_uppercase();
+    let three_window = consensus[offset..].to_ascii_uppercase();
+
+    let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
+    // gap open score: -5, gap extension score: -1
+    let mut aligner = Aligner::with_capacity(five_window.len(), three_window.len(), -5, -1, &score);
+    let alignment = aligner.local(&five_window, &three_window);
+
+    let upper = consensus.to_ascii_uppercase();
+    // local alignments can run a base or two past the real boundary
+    // by chance, so look nearby for a TG start and CA end shared by both LTR's.
+    let start_shift = snap(
+        &upper,
+        alignment.xstart,
+        offset + alignment.ystart,
+        b"TG",
+        0,
+    );
+    let end_shift = snap(&upper, alignment.xend, offset + alignment.yend, b"CA", 2);
+    let five_start = (alignment.xstart as isize + start_shift) as usize;
+    let three_start = (offset as isize + alignment.ystart as isize + start_shift) as usize;
+    // snapping can move the two LTR's into each other.
+    let five_end = ((alignment.xend as isize + end_shift) as usize).min(three_start);
+    let three_end = (offset as isize + alignment.yend as isize + end_shift) as usize;
+    let length = five_end.saturating_sub(five_start);
+
+    // score the LTR's as reported, rather than the local hit, as
+    // snapping can move the boundaries.
+    let five_ltr = upper[five_start..five_end.max(five_start)].to_vec();
+    let three_ltr = upper[three_start..three_end.max(three_start)].to_vec();
+    let alignment = aligner.global(&five_ltr, &three_ltr);
+    let (identity, mismatches, indels) = count_operations(&alignment.operations);
+
+    Ltr {
+        five_start,
+        five_end,
+        three_start,
+        three_end,
+        length,
+        identity,
+        mismatches,
+        indels,
+        five_termini: termini(&five_ltr),
+        three_termini: termini(&three_ltr),
+        pass: length >= min_length && identity >= min_identity,
+        alignment,
+        five_ltr,
+        three_ltr,
+    }
+}
+
+// how far a boundary should move (at most SLOP bases) so that `motif`
+// sits at both the 5' and 3' LTR positions. `back` is the number of
+// bases the motif sits before the boundary (two for an end).
+fn snap(seq: &[u8], five: usize, three: usize, motif: &[u8], back: usize) -> isize {
+    const SLOP: isize = 3;
+    let has_motif = |pos: isize| {
+        let start = pos - back as isize;
+        start >= 0 && seq.get(start as usize..start as usize + motif.len()) == Some(motif)
+    };
+    let mut shifts: Vec<isize> = (-SLOP..=SLOP).collect();
+    shifts.sort_by_key(|d| d.abs());
+    shifts
+        .into_iter()
+        .find(|d| has_motif(five as isize + d) && has_motif(three as isize + d))
+        .unwrap_or(0)
+}
+
+// the first and last dinucleotide of a sequence.
+fn termini(seq: &[u8]) -> (String, String) {
+    if seq.len() < 2 {
+        return (String::new(), String::new());
+    }
+    (
+        std::str::from_utf8(&seq[..2]).unwrap().to_string(),
+        std::str::from_utf8(&seq[seq.len() - 2..])
+            .unwrap()
+            .to_string(),
+    )
+}
+
+pub fn ltr(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let show_alignment = matches.is_present("show");
+    let window = value_t!(matches.value_of("window"), usize).unwrap_or_else(|e| e.exit());
+    let min_length = value_t!(matches.value_of("min_length"), usize).unwrap_or_else(|e| e.exit());
+    let min_identity = value_t!(matches.value_of("min_identity"), f64).unwrap_or_else(|e| e.exit());
+    let params = ConsensusParams::from_matches(matches, "");
+
+    // read in the alignment from file, and make the consensus
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let consensus = trimmed_consensus(alignment, &params);
+
+    let ltr = find_ltr(&consensus, window, min_length, min_identity);
+
+    println!("5' start\t5' end\t3' start\t3' end\tLength\tIdentity\tMismatches\tIndels\t5' termini\t3' termini\tTG..CA\tLTR");
+    println!(
+        "{}\t{}\t{}\t{}\t{}\t{:.3}\t{}\t{}\t{}..{}\t{}..{}\t{}\t{}",
+        ltr.five_start + 1, // add one due to zero indexing.
+        ltr.five_end,
+        ltr.three_start + 1,
+        ltr.three_end,
+        ltr.length,
+        ltr.identity,
+        ltr.mismatches,
+        ltr.indels,
+        ltr.five_termini.0,
+        ltr.five_termini.1,
+        ltr.three_termini.0,
+        ltr.three_termini.1,
+        ltr.tg_ca(),
+        if ltr.pass { "PASS" } else { "FAIL" }
+    );
+
+    if show_alignment {
+        print!("\n{}", ltr.pretty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_tg_ca_ltrs() {
+        let ltr = b"TGACCTAGGCATTCA";
+        let mut seq = ltr.to_vec();
+        seq.extend(b"G".repeat(20));
+        seq.extend(ltr);
+        let found = find_ltr(&seq, 20, 10, 0.8);
+        assert_eq!((found.five_start, found.five_end), (0, 15));
+        assert_eq!((found.three_start, found.three_end), (35, 50));
+        assert_eq!((found.length, found.mismatches, found.indels), (15, 0, 0));
+        assert_eq!(found.identity, 1.0);
+        assert!(found.tg_ca() && found.pass);
+    }
+
+    #[test]
+    fn ltrs_do_not_overlap() {
+        // the hit is GCAGTCCT twice, and snapping moves its start
+        // back one to the TG's and its end on three to the CA's.
+        let found = find_ltr(b"AAATGCAGTCCTGCAGTCCTACAA", 100, 1, 0.0);
+        assert_eq!((found.five_start, found.three_start), (3, 11));
+        assert_eq!(found.five_end, 11);
+    }
+}
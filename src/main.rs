@@ -1,13 +1,28 @@
 use clap::{App, Arg};
 use std::process;
 
-use reputils::con::make_consensus;
+use reputils::align::align;
+use reputils::batch::batch;
+use reputils::classify::classify;
+use reputils::collect::collect;
+use reputils::con::{consensus_args, make_consensus};
+use reputils::cov::cov;
 use reputils::div::diversity_windows;
+use reputils::divergence::divergence;
 use reputils::dot::dot;
+use reputils::extend::extend;
+use reputils::helitron::helitron;
 use reputils::html::render_html;
+use reputils::ltr::ltr;
+use reputils::orf::orf;
+use reputils::pep::pep;
+use reputils::stats::stats;
+use reputils::tail::tail;
 use reputils::tir::revcomp_alignment;
+use reputils::trunc::trunc;
 use reputils::tsd::find_tsds;
 use reputils::ttc::ttc;
+use reputils::utils::formats::input_format_arg;
 
 fn main() {
     let matches = App::new("reputils")
@@ -25,6 +40,7 @@ fn main() {
                         .required(true)
                         .help("The multiple alignment file in fasta format."),
                 )
+                .arg(input_format_arg())
                 .arg(
                     Arg::with_name("trim_extend")
                         .long("trim_extend")
@@ -81,50 +97,801 @@ fn main() {
                         .default_value("1")
                         .help("Number of matches to tolerate a positive match."),
                 )
+                .args(&consensus_args("con_"))
+                .arg(
+                    Arg::with_name("tir_window")
+                        .long("tir_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("100")
+                        .help("Number of bases from either end of the consensus to search for a TIR."),
+                )
+                .arg(
+                    Arg::with_name("tir_min_len")
+                        .long("tir_min_len")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("10")
+                        .help("Minimum length of a TIR arm for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("tir_min_iden")
+                        .long("tir_min_iden")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.8")
+                        .help("Minimum identity between TIR arms for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("hel_slop")
+                        .long("hel_slop")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("20")
+                        .help("How far (bp) into the consensus the 5' TC and 3' CTRR may sit."),
+                )
+                .arg(
+                    Arg::with_name("hel_distance")
+                        .long("hel_distance")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("40")
+                        .help("How far (bp) upstream of the 3' CTRR to search for the hairpin."),
+                )
+                .arg(
+                    Arg::with_name("hel_min_stem")
+                        .long("hel_min_stem")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("5")
+                        .help("Minimum length of the hairpin stem."),
+                )
+                .arg(
+                    Arg::with_name("hel_max_loop")
+                        .long("hel_max_loop")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("12")
+                        .help("Maximum length of the hairpin loop."),
+                )
+                .arg(
+                    Arg::with_name("hel_min_gc")
+                        .long("hel_min_gc")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.6")
+                        .help("Minimum GC proportion of the hairpin stem."),
+                )
+                .arg(
+                    Arg::with_name("tail_search")
+                        .long("tail_search")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("50")
+                        .help("How far (bp) either side of the 3' trim boundary a tail may start."),
+                )
+                .arg(
+                    Arg::with_name("tail_min_length")
+                        .long("tail_min_length")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("10")
+                        .help("Minimum length of a tail."),
+                )
+                .arg(
+                    Arg::with_name("tail_max_motif")
+                        .long("tail_max_motif")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("4")
+                        .help("Longest repeat unit of a tail, e.g. 1 for poly-A, 2 for (CA)n."),
+                )
+                .arg(
+                    Arg::with_name("tail_min_purity")
+                        .long("tail_min_purity")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.8")
+                        .help("Minimum proportion of the tail matching the repeat unit."),
+                )
+                .arg(
+                    Arg::with_name("trunc_min_occupancy")
+                        .long("trunc_min_occupancy")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.2")
+                        .help("Proportion of sequences which must not be a gap in a column of the core, when looking for truncated copies."),
+                )
+                .arg(
+                    Arg::with_name("trunc_slop")
+                        .long("trunc_slop")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("20")
+                        .help("Copies starting (ending) within this many columns of the core start (end) are complete at that end."),
+                )
+                .arg(
+                    Arg::with_name("trunc_bin")
+                        .long("trunc_bin")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("50")
+                        .help("Width (columns) of the copy start and end histogram bins."),
+                )
+                .arg(
+                    Arg::with_name("divergence_cpg")
+                        .long("divergence_cpg")
+                        .help("Adjust the divergence of each copy from the consensus for CpG sites."),
+                )
+                .arg(
+                    Arg::with_name("orf_min_len")
+                        .long("orf_min_len")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("100")
+                        .help("Minimum length of an ORF in amino acids."),
+                )
+                .arg(
+                    Arg::with_name("tsd_len")
+                        .long("tsd_len")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("30")
+                        .help("Number of bases from beginning or end of alignment to query."),
+                )
+                .arg(
+                    Arg::with_name("tsd_min_window")
+                        .long("tsd_min_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("2")
+                        .help("TSD's are searched for >= to this length."),
+                )
+                .arg(
+                    Arg::with_name("tsd_max_window")
+                        .long("tsd_max_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("12")
+                        .help("TSD's are searched for <= to this length."),
+                )
+                .arg(
+                    Arg::with_name("div_window_size")
+                        .long("div_window_size")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("10")
+                        .help("The size of the window to iterate over."),
+                )
+                .arg(
+                    Arg::with_name("div_window_step")
+                        .long("div_window_step")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("3")
+                        .help("The step size of the window to iterate over. If equal to window, then windows are non-overlapping."),
+                )
+                .arg(
+                    Arg::with_name("div_min_sites")
+                        .long("div_min_sites")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("5")
+                        .help("Windows where fewer sites than this are compared per pair of sequences (not gaps or N's) are left out of the diversity."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("batch")
+                .about("Run the html pipeline over a directory of alignments, writing per family outputs and a summary table.")
+                .arg(
+                    Arg::with_name("dir")
+                        .short("d")
+                        .long("dir")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Directory of multiple alignment files in fasta format, one per family."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("out")
+                        .short("o")
+                        .long("out")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("reputils_batch")
+                        .help("Directory to write the outputs to."),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short("t")
+                        .long("threads")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Number of alignments to process in parallel. Zero uses all CPU cores."),
+                )
+                .arg(
+                    Arg::with_name("trim_extend")
+                        .long("trim_extend")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("30")
+                        .help("Extend alingment either end by number of bases specified."),
+                )
+                .arg(
+                    Arg::with_name("trim_next_hit")
+                        .long("trim_next_hit")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("1")
+                        .help("Isolated hits of well conserved columns leads to bad trimming. Play with this number?"),
+                )
+                .arg(
+                    Arg::with_name("trim_miss")
+                        .long("trim_miss")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.1")
+                        .help("% missing data tolerated in a column."),
+                )
+                .arg(
+                    Arg::with_name("trim_iden")
+                        .long("trim_iden")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.85")
+                        .help("% identity in a column for the column to be considered a hit."),
+                )
+                .args(&consensus_args("con_"))
+                .arg(
+                    Arg::with_name("tir_window")
+                        .long("tir_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("100")
+                        .help("Number of bases from either end of the consensus to search for a TIR."),
+                )
+                .arg(
+                    Arg::with_name("tir_min_len")
+                        .long("tir_min_len")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("10")
+                        .help("Minimum length of a TIR arm for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("tir_min_iden")
+                        .long("tir_min_iden")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.8")
+                        .help("Minimum identity between TIR arms for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("tsd_len")
+                        .long("tsd_len")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("30")
+                        .help("Number of bases from beginning or end of alignment to query."),
+                )
+                .arg(
+                    Arg::with_name("tsd_min_window")
+                        .long("tsd_min_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("2")
+                        .help("TSD's are searched for >= to this length."),
+                )
+                .arg(
+                    Arg::with_name("tsd_max_window")
+                        .long("tsd_max_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("12")
+                        .help("TSD's are searched for <= to this length."),
+                )
+                .arg(
+                    Arg::with_name("div_window_size")
+                        .long("div_window_size")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("10")
+                        .help("The size of the window to iterate over."),
+                )
+                .arg(
+                    Arg::with_name("div_window_step")
+                        .long("div_window_step")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("3")
+                        .help("The step size of the window to iterate over. If equal to window, then windows are non-overlapping."),
+                )
+                .arg(
+                    Arg::with_name("div_min_sites")
+                        .long("div_min_sites")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("5")
+                        .help("Windows where fewer sites than this are compared per pair of sequences (not gaps or N's) are left out of the diversity."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("con")
+                .about("Make a consensus out of a multiple alignment fasta. Optimised for TE's.")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("name")
+                        .short("n")
+                        .long("name")
+                        .takes_value(true)
+                        .required(false)
+                        .default_value("CONS")
+                        .help("Name of the consensus sequence header."),
+                )
+                .arg(
+                    Arg::with_name("append")
+                        .short("a")
+                        .long("append")
+                        .help("Append the consensus to the input fasta."),
+                )
+                .args(&consensus_args(""))
+        )
+        .subcommand(
+            clap::SubCommand::with_name("tir")
+                .about("Take a consensus and quickly check for terminal inverted repeats (TIR)")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("window")
+                        .short("w")
+                        .long("window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("100")
+                        .help("Number of bases from either end of the consensus to search for a TIR."),
+                )
+                .arg(
+                    Arg::with_name("min_length")
+                        .short("l")
+                        .long("min_length")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("10")
+                        .help("Minimum length of a TIR arm for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("min_identity")
+                        .short("i")
+                        .long("min_identity")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.8")
+                        .help("Minimum identity between TIR arms for it to pass."),
+                )
+                .args(&consensus_args(""))
+                .arg(
+                    Arg::with_name("show")
+                        .short("s")
+                        .long("show")
+                        .help("Pretty print the alignment of the TIR arms."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("ltr")
+                .about("Take a consensus and quickly check for long terminal (direct) repeats (LTR)")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("window")
+                        .short("w")
+                        .long("window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("1000")
+                        .help("Number of bases from either end of the consensus to search for an LTR."),
+                )
+                .arg(
+                    Arg::with_name("min_length")
+                        .short("l")
+                        .long("min_length")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("100")
+                        .help("Minimum length of an LTR for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("min_identity")
+                        .short("i")
+                        .long("min_identity")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.8")
+                        .help("Minimum identity between the 5' and 3' LTR's for it to pass."),
+                )
+                .args(&consensus_args(""))
+                .arg(
+                    Arg::with_name("show")
+                        .short("s")
+                        .long("show")
+                        .help("Pretty print the alignment of the LTR's."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("orf")
+                .about("Find open reading frames in all six frames of the consensus")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("min_length")
+                        .short("l")
+                        .long("min_length")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("100")
+                        .help("Minimum length of an ORF in amino acids."),
+                )
+                .arg(
+                    Arg::with_name("atg")
+                        .long("atg")
+                        .help("ORF's must start with a methionine, rather than running from stop to stop."),
+                )
+                .arg(
+                    Arg::with_name("table")
+                        .short("t")
+                        .long("table")
+                        .help("Print a table of ORF coordinates instead of the protein sequences."),
+                )
+                .args(&consensus_args(""))
+        )
+        .subcommand(
+            clap::SubCommand::with_name("pep")
+                .about("Search ORF's in the consensus against a protein library (e.g. RepeatPeps.lib)")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("proteins")
+                        .short("p")
+                        .long("proteins")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The protein library in fasta format. Headers of the form name#class/family are parsed."),
+                )
+                .arg(
+                    Arg::with_name("min_length")
+                        .short("l")
+                        .long("min_length")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("50")
+                        .help("Minimum length of an ORF in amino acids."),
+                )
+                .arg(
+                    Arg::with_name("min_score")
+                        .long("min_score")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("50")
+                        .help("Minimum Smith-Waterman (BLOSUM62) score of a hit."),
+                )
+                .arg(
+                    Arg::with_name("top")
+                        .short("n")
+                        .long("top")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("10")
+                        .help("Number of hits to report."),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short("t")
+                        .long("threads")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Number of threads to search the library on. Zero uses all CPU cores."),
+                )
+                .args(&consensus_args(""))
+        )
+        .subcommand(
+            clap::SubCommand::with_name("classify")
+                .about("Make a superfamily call from the TIR's, LTR's, TSD's and terminal motifs of a TE")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("name")
+                        .short("n")
+                        .long("name")
+                        .takes_value(true)
+                        .help("Name of the TE in the label. Defaults to the file name."),
+                )
+                .arg(
+                    Arg::with_name("tir_window")
+                        .long("tir_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("100")
+                        .help("Number of bases from either end of the consensus to search for a TIR."),
+                )
+                .arg(
+                    Arg::with_name("tir_min_len")
+                        .long("tir_min_len")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("10")
+                        .help("Minimum length of a TIR arm for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("tir_min_iden")
+                        .long("tir_min_iden")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.8")
+                        .help("Minimum identity between TIR arms for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("ltr_window")
+                        .long("ltr_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("1000")
+                        .help("Number of bases from either end of the consensus to search for an LTR."),
+                )
+                .arg(
+                    Arg::with_name("ltr_min_len")
+                        .long("ltr_min_len")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("100")
+                        .help("Minimum length of an LTR for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("ltr_min_iden")
+                        .long("ltr_min_iden")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.8")
+                        .help("Minimum identity between the LTR's for it to pass."),
+                )
+                .arg(
+                    Arg::with_name("tsd_len")
+                        .long("tsd_len")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("30")
+                        .help("Number of bases from beginning or end of alignment to query."),
+                )
+                .arg(
+                    Arg::with_name("tsd_min_window")
+                        .long("tsd_min_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("2")
+                        .help("TSD's are searched for >= to this length."),
+                )
+                .arg(
+                    Arg::with_name("tsd_max_window")
+                        .long("tsd_max_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("12")
+                        .help("TSD's are searched for <= to this length."),
+                )
+                .arg(
+                    Arg::with_name("consensus")
+                        .short("c")
+                        .long("consensus")
+                        .help("Print the consensus with a name#Class/Family header instead of the table."),
+                )
+                .args(&consensus_args(""))
+        )
+        .subcommand(
+            clap::SubCommand::with_name("helitron")
+                .about("Check a consensus for the signatures of a Helitron (TC...CTRR termini, 3' hairpin, no TSD)")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("slop")
+                        .long("slop")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("20")
+                        .help("How far (bp) into the consensus the 5' TC and 3' CTRR may sit."),
+                )
+                .arg(
+                    Arg::with_name("distance")
+                        .long("distance")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("40")
+                        .help("How far (bp) upstream of the 3' CTRR to search for the hairpin."),
+                )
+                .arg(
+                    Arg::with_name("min_stem")
+                        .long("min_stem")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("5")
+                        .help("Minimum length of the hairpin stem."),
+                )
+                .arg(
+                    Arg::with_name("max_loop")
+                        .long("max_loop")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("12")
+                        .help("Maximum length of the hairpin loop."),
+                )
+                .arg(
+                    Arg::with_name("min_gc")
+                        .long("min_gc")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.6")
+                        .help("Minimum GC proportion of the hairpin stem."),
+                )
                 .arg(
                     Arg::with_name("tsd_len")
                         .long("tsd_len")
                         .takes_value(true)
                         .required(true)
-                        .default_value("30")
-                        .help("Number of bases from beginning or end of alignment to query."),
+                        .default_value("30")
+                        .help("Number of bases from beginning or end of alignment to query."),
+                )
+                .arg(
+                    Arg::with_name("tsd_min_window")
+                        .long("tsd_min_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("2")
+                        .help("TSD's are searched for >= to this length."),
+                )
+                .arg(
+                    Arg::with_name("tsd_max_window")
+                        .long("tsd_max_window")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("12")
+                        .help("TSD's are searched for <= to this length."),
+                )
+                .args(&consensus_args(""))
+        )
+        .subcommand(
+            clap::SubCommand::with_name("tail")
+                .about("Look for poly-A and other simple sequence tails at the 3' end of the consensus and each sequence")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("extend")
+                        .short("e")
+                        .long("extend")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("15")
+                        .help("Extend the extracted alignment by `e` many bases either side of the alignment."),
+                )
+                .arg(
+                    Arg::with_name("next_hit")
+                        .short("n")
+                        .long("next_hit")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("1")
+                        .help("Isolated hits of well conserved columns leads to bad trimming. Play with this number?"),
+                )
+                .arg(
+                    Arg::with_name("missing")
+                        .short("m")
+                        .long("missing")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.1")
+                        .help("% missing data tolerated in a column."),
+                )
+                .arg(
+                    Arg::with_name("identity")
+                        .short("i")
+                        .long("identity")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.8")
+                        .help("% identity in a column for the column to be considered a hit."),
                 )
                 .arg(
-                    Arg::with_name("tsd_min_window")
-                        .long("tsd_min_window")
+                    Arg::with_name("search")
+                        .long("search")
                         .takes_value(true)
                         .required(true)
-                        .default_value("2")
-                        .help("TSD's are searched for >= to this length."),
+                        .default_value("50")
+                        .help("How far (bp) either side of the 3' trim boundary a tail may start."),
                 )
                 .arg(
-                    Arg::with_name("tsd_max_window")
-                        .long("tsd_min_window")
+                    Arg::with_name("min_length")
+                        .long("min_length")
                         .takes_value(true)
                         .required(true)
-                        .default_value("12")
-                        .help("TSD's are searched for <= to this length."),
+                        .default_value("10")
+                        .help("Minimum length of a tail."),
                 )
                 .arg(
-                    Arg::with_name("div_window_size")
-                        .long("div_window_size")
+                    Arg::with_name("max_motif")
+                        .long("max_motif")
                         .takes_value(true)
                         .required(true)
-                        .default_value("10")
-                        .help("The size of the window to iterate over."),
+                        .default_value("4")
+                        .help("Longest repeat unit of a tail, e.g. 1 for poly-A, 2 for (CA)n."),
                 )
                 .arg(
-                    Arg::with_name("div_window_step")
-                        .long("div_window_step")
+                    Arg::with_name("min_purity")
+                        .long("min_purity")
                         .takes_value(true)
                         .required(true)
-                        .default_value("3")
-                        .help("The step size of the window to iterate over. If equal to window, then windows are non-overlapping."),
+                        .default_value("0.8")
+                        .help("Minimum proportion of the tail matching the repeat unit."),
                 )
+                .args(&consensus_args(""))
         )
         .subcommand(
-            clap::SubCommand::with_name("con")
-                .about("Make a consensus out of a multiple alignment fasta. Optimised for TE's.")
+            clap::SubCommand::with_name("trunc")
+                .about("Find where each copy starts and ends in the trimmed core, and count the full-length and truncated copies.")
                 .arg(
                     Arg::with_name("fasta")
                         .short("f")
@@ -133,38 +900,305 @@ fn main() {
                         .required(true)
                         .help("The multiple alignment file in fasta format."),
                 )
+                .arg(input_format_arg())
                 .arg(
-                    Arg::with_name("name")
+                    Arg::with_name("extend")
+                        .short("e")
+                        .long("extend")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Extend the trimmed core by `e` many bases either side."),
+                )
+                .arg(
+                    Arg::with_name("next_hit")
                         .short("n")
+                        .long("next_hit")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("1")
+                        .help("Isolated hits of well conserved columns leads to bad trimming. Play with this number?"),
+                )
+                .arg(
+                    Arg::with_name("min_occupancy")
+                        .short("m")
+                        .long("min_occupancy")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.2")
+                        .help("Proportion of sequences which must not be a gap in a column. Truncated copies leave gaps, so identity is among the sequences which are not."),
+                )
+                .arg(
+                    Arg::with_name("identity")
+                        .short("i")
+                        .long("identity")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0.8")
+                        .help("% identity in a column for the column to be considered a hit."),
+                )
+                .arg(
+                    Arg::with_name("slop")
+                        .short("s")
+                        .long("slop")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("20")
+                        .help("Copies starting (ending) within this many columns of the core start (end) are complete at that end."),
+                )
+                .arg(
+                    Arg::with_name("bin")
+                        .short("b")
+                        .long("bin")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("50")
+                        .help("Width (columns) of the histogram bins, also used for the modal truncation point."),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .short("d")
+                        .long("dir")
+                        .takes_value(true)
+                        .default_value(".")
+                        .help("Directory to put plot in."),
+                )
+                .arg(
+                    Arg::with_name("name")
                         .long("name")
                         .takes_value(true)
-                        .required(false)
-                        .default_value("CONS")
-                        .help("Name of the consensus sequence header."),
+                        .default_value("trunc_plot")
+                        .help("Name of the plot/PNG."),
                 )
                 .arg(
-                    Arg::with_name("append")
+                    Arg::with_name("plot")
+                        .short("p")
+                        .long("plot")
+                        .help("Plot a histogram of where copies start and end in the core. Output is a PNG."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("stats")
+                .about("Simple statistics of an alignment: lengths, gaps, GC content and consensus length.")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("format")
+                        .short("o")
+                        .long("format")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("table")
+                        .possible_values(&["table", "tsv", "json"])
+                        .help("A human readable table, a single TSV row per alignment, or JSON including each sequence."),
+                )
+                .args(&consensus_args(""))
+        )
+        .subcommand(
+            clap::SubCommand::with_name("divergence")
+                .about("Divergence of each sequence from the consensus: p-distance, Jukes-Cantor and Kimura 2-parameter.")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("cpg")
+                        .short("c")
+                        .long("cpg")
+                        .help("Adjust for CpG sites in the consensus, as RepeatMasker does: two transitions at a site count as one, and one as a tenth."),
+                )
+                .args(&consensus_args(""))
+        )
+        .subcommand(
+            clap::SubCommand::with_name("cov")
+                .about("Count the sequences which are not a gap in each column of an alignment.")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The multiple alignment file in fasta format."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("format")
+                        .short("o")
+                        .long("format")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("tsv")
+                        .possible_values(&["tsv", "bedgraph"])
+                        .help("A TSV of every column, or a bedGraph of runs of columns with the same coverage."),
+                )
+                .arg(
+                    Arg::with_name("chrom")
+                        .short("c")
+                        .long("chrom")
+                        .takes_value(true)
+                        .help("Chromosome name in the bedGraph. Defaults to the file name without its extension."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("align")
+                .about("Align unaligned sequences, e.g. TE copies from collect, without an external aligner.")
+                .arg(
+                    Arg::with_name("fasta")
+                        .short("f")
+                        .long("fasta")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The sequences in fasta format. Any gaps are removed first."),
+                )
+                .arg(input_format_arg())
+                .arg(
+                    Arg::with_name("adjust_direction")
                         .short("a")
-                        .long("append")
-                        .help("Append the consensus to the input fasta."),
-                ),
+                        .long("adjust_direction")
+                        .help("Reverse complement sequences which align better that way, adding _R_ to their names (as MAFFT --adjustdirection)."),
+                )
         )
         .subcommand(
-            clap::SubCommand::with_name("tir")
-                .about("Take a consensus and quickly check for terminal inverted repeats (TIR)")
+            clap::SubCommand::with_name("collect")
+                .about("Collect copies of a consensus from BLAST or PAF hits against the genome, with flanking sequence, ready for alignment.")
+                .arg(
+                    Arg::with_name("hits")
+                        .short("i")
+                        .long("hits")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Hits of the consensus against the genome, as BLAST tabular (-outfmt 6) or PAF."),
+                )
+                .arg(
+                    Arg::with_name("genome")
+                        .short("g")
+                        .long("genome")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The genome in fasta format, indexed with samtools faidx (genome.fa.fai)."),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short("o")
+                        .long("format")
+                        .takes_value(true)
+                        .default_value("auto")
+                        .possible_values(&["auto", "blast", "paf"])
+                        .help("Format of the hits. auto guesses from the first line."),
+                )
+                .arg(
+                    Arg::with_name("query")
+                        .short("q")
+                        .long("query")
+                        .takes_value(true)
+                        .help("Which consensus to collect copies of, if the hits are of more than one."),
+                )
+                .arg(
+                    Arg::with_name("min_identity")
+                        .long("min_identity")
+                        .takes_value(true)
+                        .default_value("0.8")
+                        .help("Minimum proportion of identical bases in a hit."),
+                )
+                .arg(
+                    Arg::with_name("min_length")
+                        .long("min_length")
+                        .takes_value(true)
+                        .default_value("100")
+                        .help("Minimum alignment length of a hit."),
+                )
+                .arg(
+                    Arg::with_name("max_evalue")
+                        .long("max_evalue")
+                        .takes_value(true)
+                        .default_value("1e-10")
+                        .help("Maximum e-value of a hit. BLAST only."),
+                )
+                .arg(
+                    Arg::with_name("merge_distance")
+                        .short("m")
+                        .long("merge_distance")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Merge hits on the same strand which overlap or are within this many bases of each other."),
+                )
+                .arg(
+                    Arg::with_name("top")
+                        .short("n")
+                        .long("top")
+                        .takes_value(true)
+                        .default_value("50")
+                        .help("Number of copies to collect, best scoring first."),
+                )
+                .arg(
+                    Arg::with_name("flank")
+                        .short("b")
+                        .long("flank")
+                        .takes_value(true)
+                        .default_value("1000")
+                        .help("Number of bases of flanking sequence either side of each copy."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("extend")
+                .about("Extract the sequences of an alignment from the genome with extra flanking sequence, ready for realignment.")
                 .arg(
                     Arg::with_name("fasta")
                         .short("f")
                         .long("fasta")
                         .takes_value(true)
                         .required(true)
-                        .help("The consensus sequence file in fasta format."),
+                        .help("The multiple alignment file in fasta format. Headers need genomic coordinates (see locus_regex)."),
                 )
+                .arg(input_format_arg())
                 .arg(
-                    Arg::with_name("show")
-                        .short("s")
-                        .long("show")
-                        .help("Pretty print the alignment."),
+                    Arg::with_name("genome")
+                        .short("g")
+                        .long("genome")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The genome in fasta format, indexed with samtools faidx (genome.fa.fai)."),
+                )
+                .arg(
+                    Arg::with_name("bases")
+                        .short("b")
+                        .long("bases")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("1000")
+                        .help("Number of bases to extend either side by."),
+                )
+                .arg(
+                    Arg::with_name("left")
+                        .short("l")
+                        .long("left")
+                        .takes_value(true)
+                        .help("Number of bases to extend the left (5') side of the alignment by, if different to bases."),
+                )
+                .arg(
+                    Arg::with_name("right")
+                        .short("r")
+                        .long("right")
+                        .takes_value(true)
+                        .help("Number of bases to extend the right (3') side of the alignment by, if different to bases."),
+                )
+                .arg(
+                    Arg::with_name("locus_regex")
+                        .long("locus_regex")
+                        .takes_value(true)
+                        .help("Regex to parse genomic coordinates from the headers, with named groups contig, start and end, and optionally strand (+ or -). e.g. '^(?P<contig>[^|]+)\\|(?P<start>\\d+)\\|(?P<end>\\d+)$'. By default, contig_start-end, contig:start-end and contig:start-end(+) are recognised."),
                 )
         )
         .subcommand(
@@ -178,6 +1212,7 @@ fn main() {
                         .required(true)
                         .help("The consensus sequence file in fasta format."),
                 )
+                .arg(input_format_arg())
                 .arg(
                     Arg::with_name("window")
                         .short("w")
@@ -196,6 +1231,20 @@ fn main() {
                         .default_value("25")
                         .help("The step size of the window to iterate over. If equal to window, then windows are non-overlapping."),
                 )
+                .arg(
+                    Arg::with_name("min_sites")
+                        .short("m")
+                        .long("min_sites")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("10")
+                        .help("Windows where fewer sites than this are compared per pair of sequences (not gaps or N's) have no per site diversity."),
+                )
+                .arg(
+                    Arg::with_name("popgen")
+                        .long("popgen")
+                        .help("Also report segregating sites, Watterson's theta per site and Tajima's D in each window, and plot them."),
+                )
                 .arg(
                     Arg::with_name("dir")
                         .short("d")
@@ -230,6 +1279,7 @@ fn main() {
                         .required(true)
                         .help("The multiple alignment sequence file in fasta format."),
                 )
+                .arg(input_format_arg())
                 .arg(
                     Arg::with_name("extend")
                         .short("e")
@@ -266,6 +1316,44 @@ fn main() {
                         .default_value("0.8")
                         .help("% identity in a column for the column to be considered a hit."),
                 )
+                .arg(
+                    Arg::with_name("locus_regex")
+                        .long("locus_regex")
+                        .takes_value(true)
+                        .help("Regex to parse genomic coordinates from the headers, with named groups contig, start and end, and optionally strand (+ or -). e.g. '^(?P<contig>[^|]+)\\|(?P<start>\\d+)\\|(?P<end>\\d+)$'. By default, contig_start-end, contig:start-end and contig:start-end(+) are recognised."),
+                )
+                .arg(
+                    Arg::with_name("stockholm")
+                        .short("s")
+                        .long("stockholm")
+                        .help("Output a Dfam style Stockholm seed alignment, with trimmed coordinates in the names, instead of fasta."),
+                )
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .takes_value(true)
+                        .help("With stockholm, the #=GF ID of the family, also the Name in GFF3 output. Defaults to the input ID, or the file name."),
+                )
+                .arg(
+                    Arg::with_name("type")
+                        .long("type")
+                        .takes_value(true)
+                        .default_value("Interspersed_Repeat;Transposable_Element")
+                        .help("With stockholm, the #=GF TP classification of the family."),
+                )
+                .arg(
+                    Arg::with_name("bed")
+                        .long("bed")
+                        .takes_value(true)
+                        .help("Write the genomic coordinates of each trimmed copy to this BED file. Needs coordinates in the headers (see locus_regex)."),
+                )
+                .arg(
+                    Arg::with_name("gff3")
+                        .long("gff3")
+                        .takes_value(true)
+                        .help("Write the genomic coordinates of each trimmed copy to this GFF3 file. Needs coordinates in the headers (see locus_regex)."),
+                )
+                .args(&consensus_args(""))
         )
         .subcommand(
             clap::SubCommand::with_name("dot")
@@ -278,6 +1366,7 @@ fn main() {
                         .required(true)
                         .help("The multiple alignment sequence file in fasta format."),
                 )
+                .arg(input_format_arg())
                 .arg(
                     Arg::with_name("wsize")
                         .short("i")
@@ -326,6 +1415,7 @@ fn main() {
                         .required(true)
                         .help("The multiple alignment sequence file in fasta format."),
                 )
+                .arg(input_format_arg())
                 .arg(
                     Arg::with_name("length")
                         .short("l")
@@ -366,6 +1456,58 @@ fn main() {
             let matches = subcommand.1.unwrap();
             revcomp_alignment(matches);
         }
+        "ltr" => {
+            let matches = subcommand.1.unwrap();
+            ltr(matches);
+        }
+        "orf" => {
+            let matches = subcommand.1.unwrap();
+            orf(matches);
+        }
+        "pep" => {
+            let matches = subcommand.1.unwrap();
+            pep(matches);
+        }
+        "classify" => {
+            let matches = subcommand.1.unwrap();
+            classify(matches);
+        }
+        "helitron" => {
+            let matches = subcommand.1.unwrap();
+            helitron(matches);
+        }
+        "tail" => {
+            let matches = subcommand.1.unwrap();
+            tail(matches);
+        }
+        "trunc" => {
+            let matches = subcommand.1.unwrap();
+            trunc(matches);
+        }
+        "stats" => {
+            let matches = subcommand.1.unwrap();
+            stats(matches);
+        }
+        "divergence" => {
+            let matches = subcommand.1.unwrap();
+            divergence(matches);
+        }
+        "cov" => {
+            let matches = subcommand.1.unwrap();
+            cov(matches);
+        }
+        "align" => {
+            let matches = subcommand.1.unwrap();
+            align(matches);
+        }
+        "collect" => {
+            let matches = subcommand.1.unwrap();
+            collect(matches);
+        }
+        "extend" => {
+            let matches = subcommand.1.unwrap();
+            extend(matches);
+        }
         "div" => {
             let matches = subcommand.1.unwrap();
             diversity_windows(matches);
@@ -382,6 +1524,10 @@ fn main() {
             let matches = subcommand.1.unwrap();
             dot(matches).unwrap();
         }
+        "batch" => {
+            let matches = subcommand.1.unwrap();
+            batch(matches);
+        }
         "html" => {
             let matches = subcommand.1.unwrap();
             render_html(matches);
@@ -0,0 +1,174 @@
+use clap::value_t;
+
+use crate::con::{trimmed_consensus, ConsensusParams, WriteSequence};
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+use crate::utils::revcomp::reverse_complement;
+use crate::utils::translate::translate;
+
+// look for coding capacity (transposase, RT, integrase...) in the consensus.
+// the consensus is translated in all six frames, and the stretches between
+// stop codons are reported.
+
+/// An open reading frame in the consensus. Coordinates are zero
+/// based, half open, and on the forward strand of the gap
+/// stripped consensus, including the stop codon if there is one.
+#[derive(Debug, Clone)]
+pub struct Orf {
+    pub strand: char,
+    /// 1, 2 or 3
+    pub frame: usize,
+    pub start: usize,
+    pub end: usize,
+    pub protein: Vec<u8>,
+}
+
+impl Orf {
+    pub fn len(&self) -> usize {
+        self.protein.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Find the ORF's of at least `min_length` amino acids in all six
+/// frames of `seq`, longest first. ORF's run from stop to stop (or the end
+/// of the sequence), unless `atg` is set, in which case they start at the
+/// first methionine.
+pub fn find_orfs(seq: &[u8], min_length: usize, atg: bool) -> Vec<Orf> {
+    let len = seq.len();
+    let reverse = reverse_complement(seq);
+    let mut orfs = Vec::new();
+
+    for (strand, strand_seq) in [('+', seq), ('-', &reverse[..])] {
+        for offset in 0..3 {
+            if offset >= len {
+                continue;
+            }
+            let protein = translate(&strand_seq[offset..]);
+
+            // aa index where the current ORF began
+            let mut begin = 0;
+            for i in 0..=protein.len() {
+                let stop = i < protein.len() && protein[i] == b'*';
+                if i < protein.len() && !stop {
+                    continue;
+                }
+                let start = if atg {
+                    match protein[begin..i].iter().position(|&aa| aa == b'M') {
+                        Some(m) => begin + m,
+                        None => {
+                            begin = i + 1;
+                            continue;
+                        }
+                    }
+                } else {
+                    begin
+                };
+
+                if i - start >= min_length {
+                    // nucleotide coordinates on this strand, with the stop codon.
+                    let nt_start = offset + 3 * start;
+                    let nt_end = offset + 3 * i + if stop { 3 } else { 0 };
+                    let (start_fwd, end_fwd) = match strand {
+                        '+' => (nt_start, nt_end),
+                        _ => (len - nt_end, len - nt_start),
+                    };
+                    orfs.push(Orf {
+                        strand,
+                        frame: offset + 1,
+                        start: start_fwd,
+                        end: end_fwd,
+                        protein: protein[start..i].to_vec(),
+                    });
+                }
+                begin = i + 1;
+            }
+        }
+    }
+    orfs.sort_by_key(|o| std::cmp::Reverse(o.len()));
+    orfs
+}
+
+pub fn orf(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let min_length = value_t!(matches.value_of("min_length"), usize).unwrap_or_else(|e| e.exit());
+    let atg = matches.is_present("atg");
+    let table = matches.is_present("table");
+    let params = ConsensusParams::from_matches(matches, "");
+
+    // read in the alignment from file, and make the consensus
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let consensus = trimmed_consensus(alignment, &params);
+
+    let orfs = find_orfs(&consensus, min_length, atg);
+
+    if table {
+        println!("ORF\tStrand\tFrame\tStart\tEnd\tLength (aa)");
+    }
+    for (i, orf) in orfs.iter().enumerate() {
+        if table {
+            println!(
+                "ORF{}\t{}\t{}\t{}\t{}\t{}",
+                i + 1,
+                orf.strand,
+                orf.frame,
+                orf.start + 1, // add one due to zero indexing.
+                orf.end,
+                orf.len()
+            );
+        } else {
+            println!(
+                ">ORF{} strand={} frame={} start={} end={} length={}\n{}",
+                i + 1,
+                orf.strand,
+                orf.frame,
+                orf.start + 1,
+                orf.end,
+                orf.len(),
+                WriteSequence(orf.protein.clone())
+            );
+        }
+    }
+
+    if orfs.is_empty() {
+        eprintln!(
+            "[-]\tNo ORF's of at least {} amino acids found.",
+            min_length
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reverse_strand_coordinates() {
+        // ATG AAA TAA on the reverse strand.
+        let seq = b"GGTTATTTCATGG";
+        let orfs = find_orfs(seq, 2, true);
+        assert_eq!(orfs.len(), 1);
+        let orf = &orfs[0];
+        assert_eq!((orf.strand, orf.frame), ('-', 3));
+        assert_eq!((orf.start, orf.end), (2, 11));
+        assert_eq!(orf.protein, b"MK");
+        assert_eq!(&seq[orf.start..orf.end], b"TTATTTCAT");
+    }
+
+    #[test]
+    fn coordinates_translate_back() {
+        let seq = b"GATGTTACCTTAGGCATTAACGGATCCTAGTTTGACATGC";
+        for orf in find_orfs(seq, 1, false) {
+            let region = &seq[orf.start..orf.end];
+            let region = match orf.strand {
+                '+' => region.to_vec(),
+                _ => reverse_complement(region),
+            };
+            let protein = translate(&region);
+            let protein = protein.strip_suffix(b"*").unwrap_or(&protein);
+            assert_eq!(protein, &orf.protein[..], "{:?}", orf);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use bio::alignment::pairwise::*;
+use bio::io::fasta;
+use bio::scores::blosum62;
+use clap::value_t;
+use std::path::Path;
+use std::thread;
+
+use crate::con::{trimmed_consensus, ConsensusParams};
+use crate::orf::{find_orfs, Orf};
+use crate::tir::count_operations;
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+
+// search the ORF's of the consensus against a protein library,
+// e.g. RepeatPeps.lib from RepeatMasker, with a Smith-Waterman aligner.
+// no BLAST needed, but it is an exhaustive search, so use the threads.
+
+// BLAST defaults for BLOSUM62, a gap of length k scores -11 - k.
+const GAP_OPEN: i32 = -11;
+const GAP_EXTEND: i32 = -1;
+
+/// A protein from the library, with the repeat class and family
+/// parsed from a RepeatMasker style header, e.g. `Tc1-1_CB#DNA/TcMar-Tc1`.
+#[derive(Debug, Clone)]
+pub struct Protein {
+    pub name: String,
+    pub class: String,
+    pub family: String,
+    pub sequence: Vec<u8>,
+}
+
+/// The best local alignment of an ORF to a library protein.
+/// Coordinates are zero based and half open.
+#[derive(Debug, Clone)]
+pub struct ProteinHit {
+    /// index into the ORF's searched
+    pub orf: usize,
+    pub name: String,
+    pub class: String,
+    pub family: String,
+    pub score: i32,
+    pub identity: f64,
+    pub query_start: usize,
+    pub query_end: usize,
+    pub subject_start: usize,
+    pub subject_end: usize,
+    /// proportion of the ORF in the alignment
+    pub query_coverage: f64,
+    /// proportion of the library protein in the alignment
+    pub subject_coverage: f64,
+}
+
+/// Split a header into name, class and family. Headers without a
+/// class are Unknown, and classes without a family (e.g. `#LINE`)
+/// have an empty family.
+pub fn parse_repeat_class(header: &str) -> (String, String, String) {
+    let (name, classification) = match header.split_once('#') {
+        Some((n, c)) => (n, c),
+        None => (header, "Unknown"),
+    };
+    let (class, family) = classification
+        .split_once('/')
+        .unwrap_or((classification, ""));
+    (name.to_string(), class.to_string(), family.to_string())
+}
+
+/// Read a protein fasta. Residues are upper cased, and anything
+/// BLOSUM62 does not know becomes an X.
+pub fn read_proteins<P: AsRef<Path> + std::fmt::Debug>(path: P) -> Vec<Protein> {
+    let reader = fasta::Reader::from_file(path).expect("[-]\tCould not open protein library.");
+    reader
+        .records()
+        .map(|record| {
+            let record = record.expect("[-]\tCould not read protein library record.");
+            let (name, class, family) = parse_repeat_class(record.id());
+            let sequence = record
+                .seq()
+                .iter()
+                .map(|aa| match aa.to_ascii_uppercase() {
+                    aa @ (b'A'..=b'Z' | b'*') => aa,
+                    _ => b'X',
+                })
+                .collect();
+            Protein {
+                name,
+                class,
+                family,
+                sequence,
+            }
+        })
+        .collect()
+}
+
+/// Align every ORF against every library protein, keeping hits scoring
+/// at least `min_score`, best first. The library is split between `threads`
+/// workers.
+pub fn search_proteins(
+    orfs: &[Orf],
+    library: &[Protein],
+    min_score: i32,
+    threads: usize,
+) -> Vec<ProteinHit> {
+    if library.is_empty() || orfs.is_empty() {
+        return Vec::new();
+    }
+    let chunk_size = library.len().div_ceil(threads.max(1));
+
+    let mut hits: Vec<ProteinHit> = thread::scope(|s| {
+        let workers: Vec<_> = library
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move || search_chunk(orfs, chunk, min_score)))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect()
+    });
+
+    hits.sort_by(|a, b| b.score.cmp(&a.score).then(a.orf.cmp(&b.orf)));
+    hits
+}
+
+fn search_chunk(orfs: &[Orf], chunk: &[Protein], min_score: i32) -> Vec<ProteinHit> {
+    let max_len = chunk.iter().map(|p| p.sequence.len()).max().unwrap_or(0);
+    let mut aligner =
+        Aligner::with_capacity(orfs[0].len(), max_len, GAP_OPEN, GAP_EXTEND, &blosum62);
+
+    let mut hits = Vec::new();
+    for protein in chunk {
+        if protein.sequence.is_empty() {
+            continue;
+        }
+        for (index, orf) in orfs.iter().enumerate() {
+            let alignment = aligner.local(&orf.protein, &protein.sequence);
+            if alignment.score < min_score {
+                continue;
+            }
+            let (identity, _, _) = count_operations(&alignment.operations);
+            hits.push(ProteinHit {
+                orf: index,
+                name: protein.name.clone(),
+                class: protein.class.clone(),
+                family: protein.family.clone(),
+                score: alignment.score,
+                identity,
+                query_start: alignment.xstart,
+                query_end: alignment.xend,
+                subject_start: alignment.ystart,
+                subject_end: alignment.yend,
+                query_coverage: (alignment.xend - alignment.xstart) as f64 / orf.len() as f64,
+                subject_coverage: (alignment.yend - alignment.ystart) as f64
+                    / protein.sequence.len() as f64,
+            });
+        }
+    }
+    hits
+}
+
+pub fn pep(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let proteins = matches.value_of("proteins").unwrap();
+    let min_length = value_t!(matches.value_of("min_length"), usize).unwrap_or_else(|e| e.exit());
+    let min_score = value_t!(matches.value_of("min_score"), i32).unwrap_or_else(|e| e.exit());
+    let top = value_t!(matches.value_of("top"), usize).unwrap_or_else(|e| e.exit());
+    let threads = value_t!(matches.value_of("threads"), usize).unwrap_or_else(|e| e.exit());
+    let params = ConsensusParams::from_matches(matches, "");
+
+    // zero threads means use all of them.
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        t => t,
+    };
+
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let consensus = trimmed_consensus(alignment, &params);
+    let orfs = find_orfs(&consensus, min_length, false);
+    if orfs.is_empty() {
+        eprintln!(
+            "[-]\tNo ORF's of at least {} amino acids found. Exiting.",
+            min_length
+        );
+        std::process::exit(1);
+    }
+
+    let library = read_proteins(proteins);
+    eprintln!(
+        "[+]\tSearching {} ORF's against {} proteins on {} threads.",
+        orfs.len(),
+        library.len(),
+        threads
+    );
+    let hits = search_proteins(&orfs, &library, min_score, threads);
+
+    println!("ORF\tStrand\tFrame\tORF start\tORF end\tHit\tClass\tFamily\tScore\tIdentity\tORF coverage\tHit coverage\tHit start\tHit end");
+    for hit in hits.iter().take(top) {
+        let orf = &orfs[hit.orf];
+        println!(
+            "ORF{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.3}\t{:.3}\t{:.3}\t{}\t{}",
+            hit.orf + 1,
+            orf.strand,
+            orf.frame,
+            orf.start + 1, // add one due to zero indexing.
+            orf.end,
+            hit.name,
+            hit.class,
+            if hit.family.is_empty() {
+                "-"
+            } else {
+                &hit.family
+            },
+            hit.score,
+            hit.identity,
+            hit.query_coverage,
+            hit.subject_coverage,
+            hit.subject_start + 1,
+            hit.subject_end
+        );
+    }
+
+    if hits.is_empty() {
+        eprintln!("[-]\tNo hits scoring at least {}.", min_score);
+    }
+}
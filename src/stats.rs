@@ -0,0 +1,250 @@
+use crate::con::{get_consensus, ConsensusParams};
+use crate::utils::alignment::{Alignment, Sequence};
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+
+// simple statistics of an alignment, and a check it really is one.
+
+/// Statistics of one sequence in the alignment.
+#[derive(Debug, Clone)]
+pub struct SequenceStats {
+    pub name: String,
+    /// length including gaps.
+    pub length: usize,
+    pub ungapped: usize,
+    pub gap_fraction: f64,
+    /// proportion of G or C among the A, C, G and T's.
+    pub gc: f64,
+}
+
+/// Statistics of a whole alignment. Lengths are of the ungapped sequences.
+#[derive(Debug, Clone)]
+pub struct AlignmentStats {
+    pub sequences: usize,
+    /// all sequences are the same length.
+    pub aligned: bool,
+    /// the length of the longest row.
+    pub length: usize,
+    pub min_length: usize,
+    pub median_length: f64,
+    pub max_length: usize,
+    pub n50: usize,
+    pub gap_fraction: f64,
+    pub gc: f64,
+    /// length of the consensus without gaps, if aligned.
+    pub consensus_length: Option<usize>,
+    pub per_sequence: Vec<SequenceStats>,
+}
+
+// (G or C, A C G or T) counts.
+fn gc_counts(sequence: &[u8]) -> (usize, usize) {
+    sequence
+        .iter()
+        .fold((0, 0), |(gc, acgt), b| match b.to_ascii_uppercase() {
+            b'G' | b'C' => (gc + 1, acgt + 1),
+            b'A' | b'T' => (gc, acgt + 1),
+            _ => (gc, acgt),
+        })
+}
+
+// zero rather than NaN for empty sequences.
+fn proportion(a: usize, b: usize) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+/// The length such that sequences at least this long hold at least
+/// half of the bases.
+pub fn n50(lengths: &[usize]) -> usize {
+    let mut sorted = lengths.to_vec();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+    let total: usize = sorted.iter().sum();
+    let mut running = 0;
+    for length in sorted {
+        running += length;
+        if running * 2 >= total {
+            return length;
+        }
+    }
+    0
+}
+
+fn median(lengths: &[usize]) -> f64 {
+    let mut sorted = lengths.to_vec();
+    sorted.sort_unstable();
+    match sorted.len() {
+        0 => 0.0,
+        n if n % 2 == 1 => sorted[n / 2] as f64,
+        n => (sorted[n / 2 - 1] + sorted[n / 2]) as f64 / 2.0,
+    }
+}
+
+fn sequence_stats(record: &Sequence) -> SequenceStats {
+    let ungapped = record.sequence.iter().filter(|&&b| b != b'-').count();
+    let (gc, acgt) = gc_counts(&record.sequence);
+    SequenceStats {
+        name: record.name.clone(),
+        length: record.len(),
+        ungapped,
+        gap_fraction: proportion(record.len() - ungapped, record.len()),
+        gc: proportion(gc, acgt),
+    }
+}
+
+/// Calculate the statistics. The consensus is only made if all the
+/// sequences are the same length.
+pub fn alignment_stats(alignment: &Alignment, params: &ConsensusParams) -> AlignmentStats {
+    let per_sequence: Vec<SequenceStats> = alignment.matrix.iter().map(sequence_stats).collect();
+    let lengths: Vec<usize> = per_sequence.iter().map(|s| s.ungapped).collect();
+    let aligned = alignment.is_aligned();
+
+    let total: usize = per_sequence.iter().map(|s| s.length).sum();
+    let ungapped: usize = lengths.iter().sum();
+    let (gc, acgt) = alignment
+        .matrix
+        .iter()
+        .map(|r| gc_counts(&r.sequence))
+        .fold((0, 0), |(a, b), (c, d)| (a + c, b + d));
+
+    let consensus_length = (aligned && !alignment.matrix.is_empty()).then(|| {
+        get_consensus(
+            &alignment.get_profile(),
+            alignment.matrix.len() as i32,
+            params,
+        )
+        .iter()
+        .filter(|&&b| b != b'-')
+        .count()
+    });
+
+    AlignmentStats {
+        sequences: alignment.matrix.len(),
+        aligned,
+        length: per_sequence.iter().map(|s| s.length).max().unwrap_or(0),
+        min_length: lengths.iter().min().copied().unwrap_or(0),
+        median_length: median(&lengths),
+        max_length: lengths.iter().max().copied().unwrap_or(0),
+        n50: n50(&lengths),
+        gap_fraction: proportion(total - ungapped, total),
+        gc: proportion(gc, acgt),
+        consensus_length,
+        per_sequence,
+    }
+}
+
+// escape a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+impl AlignmentStats {
+    /// The statistics as JSON, with an object per sequence.
+    pub fn to_json(&self) -> String {
+        let sequences: Vec<String> = self
+            .per_sequence
+            .iter()
+            .map(|s| {
+                format!(
+                    "{{\"name\":{},\"length\":{},\"ungapped\":{},\"gap_fraction\":{:.4},\"gc\":{:.4}}}",
+                    json_string(&s.name),
+                    s.length,
+                    s.ungapped,
+                    s.gap_fraction,
+                    s.gc
+                )
+            })
+            .collect();
+        format!(
+            "{{\"sequences\":{},\"aligned\":{},\"length\":{},\"min_length\":{},\"median_length\":{},\"max_length\":{},\"n50\":{},\"gap_fraction\":{:.4},\"gc\":{:.4},\"consensus_length\":{},\"per_sequence\":[{}]}}",
+            self.sequences,
+            self.aligned,
+            self.length,
+            self.min_length,
+            self.median_length,
+            self.max_length,
+            self.n50,
+            self.gap_fraction,
+            self.gc,
+            self.consensus_length
+                .map_or("null".to_string(), |l| l.to_string()),
+            sequences.join(",")
+        )
+    }
+}
+
+pub fn stats(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let format = matches.value_of("format").unwrap();
+    let params = ConsensusParams::from_matches(matches, "");
+
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let stats = alignment_stats(&alignment, &params);
+    let consensus_length = stats
+        .consensus_length
+        .map_or("-".to_string(), |l| l.to_string());
+
+    match format {
+        "json" => println!("{}", stats.to_json()),
+        // one row per alignment, so they can be concatenated.
+        "tsv" => {
+            println!("File\tSequences\tAligned\tLength\tMin length\tMedian length\tMax length\tN50\tGap fraction\tGC\tConsensus length");
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.4}\t{:.4}\t{}",
+                fasta,
+                stats.sequences,
+                stats.aligned,
+                stats.length,
+                stats.min_length,
+                stats.median_length,
+                stats.max_length,
+                stats.n50,
+                stats.gap_fraction,
+                stats.gc,
+                consensus_length
+            );
+        }
+        _ => {
+            println!("Sequences:\t\t{}", stats.sequences);
+            println!(
+                "Alignment length:\t{}{}",
+                stats.length,
+                if stats.aligned {
+                    ""
+                } else {
+                    " (sequences are not all the same length!)"
+                }
+            );
+            println!(
+                "Ungapped length:\tmin {}, median {}, max {}, N50 {}",
+                stats.min_length, stats.median_length, stats.max_length, stats.n50
+            );
+            println!("Gap fraction:\t\t{:.4}", stats.gap_fraction);
+            println!("GC content:\t\t{:.4}", stats.gc);
+            println!("Consensus length:\t{}", consensus_length);
+            println!();
+            println!("Sequence\tLength\tUngapped\tGap fraction\tGC");
+            for s in &stats.per_sequence {
+                println!(
+                    "{}\t{}\t{}\t{:.4}\t{:.4}",
+                    s.name, s.length, s.ungapped, s.gap_fraction, s.gc
+                );
+            }
+        }
+    }
+
+    if !stats.aligned {
+        eprintln!("[-]\tAll sequences in the fasta file are not the same length.");
+    }
+}
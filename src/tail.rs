@@ -0,0 +1,297 @@
+use clap::value_t;
+use std::fmt;
+use std::ops::Range;
+
+use crate::con::{get_consensus, ConsensusParams};
+use crate::utils::alignment::Alignment;
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+
+// non-LTR retrotransposons (LINE's, SINE's) end in a poly-A tail,
+// or another simple repeat. these align badly, so are often trimmed off,
+// hence also saying where each tail sits relative to the trim boundaries.
+
+/// Parameters for the tail search.
+#[derive(Debug, Clone, Copy)]
+pub struct TailParams {
+    /// how far (bp) either side of the 3' trim boundary a tail may start.
+    pub search: usize,
+    pub min_length: usize,
+    /// longest repeat unit, e.g. 1 for poly-A, 2 for (CA)n.
+    pub max_motif: usize,
+    /// minimum proportion of the tail matching the repeat.
+    pub min_purity: f64,
+}
+
+impl Default for TailParams {
+    fn default() -> Self {
+        TailParams {
+            search: 50,
+            min_length: 10,
+            max_motif: 4,
+            min_purity: 0.8,
+        }
+    }
+}
+
+impl TailParams {
+    /// Read the parameters from the command line. `prefix` is prepended
+    /// to each argument name, e.g. "tail_" in `reputils html`.
+    pub fn from_matches(matches: &clap::ArgMatches, prefix: &str) -> Self {
+        let arg = |name: &str| format!("{}{}", prefix, name);
+
+        TailParams {
+            search: value_t!(matches.value_of(&arg("search")), usize).unwrap_or_else(|e| e.exit()),
+            min_length: value_t!(matches.value_of(&arg("min_length")), usize)
+                .unwrap_or_else(|e| e.exit()),
+            max_motif: value_t!(matches.value_of(&arg("max_motif")), usize)
+                .unwrap_or_else(|e| e.exit()),
+            min_purity: value_t!(matches.value_of(&arg("min_purity")), f64)
+                .unwrap_or_else(|e| e.exit()),
+        }
+    }
+}
+
+/// A simple sequence tail. Coordinates are zero based, half open,
+/// and in the sequence without gaps.
+#[derive(Debug, Clone)]
+pub struct Tail {
+    /// the repeat unit, in the phase it starts in.
+    pub motif: String,
+    pub start: usize,
+    pub end: usize,
+    pub purity: f64,
+    score: i32,
+}
+
+impl Tail {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Where a tail sits relative to the 3' trim boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimPosition {
+    Inside,
+    Spanning,
+    Outside,
+}
+
+impl fmt::Display for TrimPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let position = match self {
+            TrimPosition::Inside => "inside",
+            TrimPosition::Spanning => "spanning",
+            TrimPosition::Outside => "outside",
+        };
+        write!(f, "{}", position)
+    }
+}
+
+/// A tail in an aligned sequence, with the alignment columns it
+/// covers (half open), and where it sits relative to the trim boundary.
+#[derive(Debug, Clone)]
+pub struct AlignedTail {
+    pub tail: Tail,
+    pub column_start: usize,
+    pub column_end: usize,
+    pub position: TrimPosition,
+}
+
+// is the motif a repeat of a shorter one (e.g. AA, ACAC)?
+fn primitive(motif: &[u8]) -> bool {
+    let k = motif.len();
+    (1..k).all(|d| k % d != 0 || motif.chunks(d).any(|c| c != &motif[..d]))
+}
+
+/// The best scoring simple repeat starting in `starts`. Repeats are
+/// extended base by base (+1 for a match, -3 for a mismatch) until the
+/// score drops well below its best, so short interruptions are tolerated.
+pub fn find_tail(seq: &[u8], starts: Range<usize>, params: &TailParams) -> Option<Tail> {
+    const MISMATCH: i32 = -3;
+    const X_DROP: i32 = 6;
+    let seq = seq.to_ascii_uppercase();
+    let mut best: Option<Tail> = None;
+
+    for start in starts.start..starts.end.min(seq.len()) {
+        for k in 1..=params.max_motif.min(seq.len() - start) {
+            let motif = &seq[start..start + k];
+            if !motif.iter().all(|b| b"ACGT".contains(b)) || !primitive(motif) {
+                continue;
+            }
+            let (mut score, mut top, mut end, mut matches, mut top_matches) = (0, 0, start, 0, 0);
+            for (i, &base) in seq.iter().enumerate().skip(start) {
+                if base == motif[(i - start) % k] {
+                    score += 1;
+                    matches += 1;
+                } else {
+                    score += MISMATCH;
+                }
+                if score > top {
+                    top = score;
+                    end = i + 1;
+                    top_matches = matches;
+                }
+                if top - score > X_DROP {
+                    break;
+                }
+            }
+
+            let length = end - start;
+            let purity = top_matches as f64 / length as f64;
+            if length < params.min_length || purity < params.min_purity {
+                continue;
+            }
+            if best.as_ref().map_or(true, |b| top > b.score) {
+                best = Some(Tail {
+                    motif: String::from_utf8_lossy(motif).to_string(),
+                    start,
+                    end,
+                    purity,
+                    score: top,
+                });
+            }
+        }
+    }
+    best
+}
+
+/// Look for a tail in an aligned (gapped) sequence, starting within
+/// `params.search` columns either side of the 3' trim boundary `trim_end`,
+/// so simple repeats far out in the flank are not taken for the tail.
+pub fn find_aligned_tail(
+    sequence: &[u8],
+    trim_end: usize,
+    params: &TailParams,
+) -> Option<AlignedTail> {
+    let (bases, columns): (Vec<u8>, Vec<usize>) = sequence
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b != b'-')
+        .map(|(i, &b)| (b, i))
+        .unzip();
+    let from = columns.partition_point(|&c| c < trim_end.saturating_sub(params.search));
+    let to = columns.partition_point(|&c| c < trim_end + params.search);
+
+    let tail = find_tail(&bases, from..to, params)?;
+    let column_start = columns[tail.start];
+    let column_end = columns[tail.end - 1] + 1;
+    let position = if column_end <= trim_end {
+        TrimPosition::Inside
+    } else if column_start >= trim_end {
+        TrimPosition::Outside
+    } else {
+        TrimPosition::Spanning
+    };
+    Some(AlignedTail {
+        tail,
+        column_start,
+        column_end,
+        position,
+    })
+}
+
+/// Tails in the consensus of the whole (untrimmed) alignment, and in
+/// each aligned sequence. `trim_end` is the 3' trim boundary.
+pub fn alignment_tails(
+    alignment: &Alignment,
+    trim_end: usize,
+    consensus_params: &ConsensusParams,
+    params: &TailParams,
+) -> (Option<AlignedTail>, Vec<(String, Option<AlignedTail>)>) {
+    // the gapped consensus lines up with the alignment columns.
+    let consensus = get_consensus(
+        &alignment.get_profile(),
+        alignment.matrix.len() as i32,
+        consensus_params,
+    );
+    let consensus_tail = find_aligned_tail(&consensus, trim_end, params);
+
+    let tails = alignment
+        .matrix
+        .iter()
+        .map(|record| {
+            (
+                record.name.clone(),
+                find_aligned_tail(&record.sequence, trim_end, params),
+            )
+        })
+        .collect();
+    (consensus_tail, tails)
+}
+
+pub fn tail(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let extend = value_t!(matches.value_of("extend"), usize).unwrap_or_else(|e| e.exit());
+    let next_hit = value_t!(matches.value_of("next_hit"), usize).unwrap_or_else(|e| e.exit());
+    let miss = value_t!(matches.value_of("missing"), f64).unwrap_or_else(|e| e.exit());
+    let iden = value_t!(matches.value_of("identity"), f64).unwrap_or_else(|e| e.exit());
+    let params = TailParams::from_matches(matches, "");
+    let consensus_params = ConsensusParams::from_matches(matches, "");
+
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let (_, trim_end) =
+        alignment
+            .find_blocks(miss, iden)
+            .trim_bounds(alignment.matrix[0].len(), extend, next_hit);
+    let (consensus_tail, tails) = alignment_tails(&alignment, trim_end, &consensus_params, &params);
+
+    println!("Sequence\tMotif\tLength\tPurity\tStart\tEnd\tColumn start\tColumn end\tTrim");
+    let row = |name: &str, tail: &Option<AlignedTail>| match tail {
+        Some(t) => println!(
+            "{}\t{}\t{}\t{:.2}\t{}\t{}\t{}\t{}\t{}",
+            name,
+            t.tail.motif,
+            t.tail.len(),
+            t.tail.purity,
+            t.tail.start + 1, // add one due to zero indexing.
+            t.tail.end,
+            t.column_start + 1,
+            t.column_end,
+            t.position
+        ),
+        None => println!("{}\t-\t-\t-\t-\t-\t-\t-\t-", name),
+    };
+    row("consensus", &consensus_tail);
+    for (name, tail) in &tails {
+        row(name, tail);
+    }
+
+    eprintln!(
+        "[+]\t{} of {} sequences have a 3' tail. The 3' trim boundary is at column {}.",
+        tails.iter().filter(|(_, t)| t.is_some()).count(),
+        tails.len(),
+        trim_end
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_a_poly_a_tail() {
+        let seq = b"GCTAGCTTGCAAAAAAAAAAAAGAAAAAGCT";
+        let tail = find_tail(seq, 0..seq.len(), &TailParams::default()).unwrap();
+        assert_eq!((tail.motif.as_str(), tail.start, tail.end), ("A", 10, 28));
+        assert!(primitive(b"ACG") && !primitive(b"ACAC") && !primitive(b"AA"));
+    }
+
+    #[test]
+    fn tails_start_near_the_trim_boundary() {
+        // a poly-A tail at the boundary, and a longer (CTC)n far out in the flank.
+        let mut seq = b"GCTAGCTTGC".repeat(10);
+        seq.extend(b"A".repeat(15));
+        seq.extend(b"GCTAGCTTGC".repeat(10));
+        seq.extend(b"CTC".repeat(20));
+        let params = TailParams::default();
+        let tail = find_aligned_tail(&seq, 100, &params).unwrap();
+        assert_eq!((tail.tail.motif.as_str(), tail.column_start), ("A", 100));
+        assert_eq!(tail.position, TrimPosition::Outside);
+        assert!(find_aligned_tail(&seq, 40, &params).is_none());
+    }
+}
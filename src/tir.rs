@@ -1,78 +1,189 @@
 use bio::alignment::pairwise::*;
-use bio::io::fasta;
+use bio::alignment::AlignmentOperation;
+use clap::value_t;
 
-use crate::con::get_consensus;
-use crate::utils::alignment::{Alignment, Sequence};
+use crate::con::{trimmed_consensus, ConsensusParams};
+use crate::utils::formats::{format_from_matches, read_alignment_as};
 use crate::utils::revcomp::reverse_complement;
 use crate::utils::seqcount::SequentialCount;
 
-// use this module to perform an alignment between consensus and itself (but revcomp)
-// might be swift evidence of a TIR.
-// TODO: might be useful to trim the pretty alignment? Here or in the HTML.
+// use this module to perform an alignment between the ends of the consensus and
+// the reverse complement of the other end. A well supported hit at the termini
+// is swift evidence of a TIR.
 
 // maybe this should take the whole alignment, trim (optional), make consensus, then do self alignment.
 
-pub fn revcomp_alignment(matches: &clap::ArgMatches) {
-    let fasta = matches.value_of("fasta").unwrap();
-    let show_alignment = matches.is_present("show");
+/// The result of searching the termini of a consensus for
+/// terminal inverted repeats. Coordinates are zero based,
+/// half open, and in the gap stripped consensus.
+#[derive(Debug, Clone)]
+pub struct Tir {
+    pub left_start: usize,
+    pub left_end: usize,
+    pub right_start: usize,
+    pub right_end: usize,
+    pub length: usize,
+    pub identity: f64,
+    pub mismatches: usize,
+    pub indels: usize,
+    pub pass: bool,
+    // kept so the arms can be pretty printed.
+    pub alignment: bio::alignment::Alignment,
+    pub left_arm: Vec<u8>,
+    pub right_arm: Vec<u8>,
+}
 
-    // read in the fasta from file
-    let mut reader = fasta::Reader::from_file(fasta)
-        .expect("[-]\tPath invalid.")
-        .records();
+impl Tir {
+    /// Pretty print the alignment of the left arm against the
+    /// reverse complement of the right arm.
+    pub fn pretty(&self) -> String {
+        self.alignment.pretty(&self.left_arm, &self.right_arm)
+    }
+}
 
-    let mut read_number = 0i32;
+/// Locally align the first `window` bases of the consensus to the
+/// reverse complement of the last `window` bases. The best local hit
+/// is reported as the putative TIR, and passes if it is at least
+/// `min_length` long and `min_identity` identical.
+pub fn find_tir(consensus: &[u8], window: usize, min_length: usize, min_identity: f64) -> Tir {
+    let len = consensus.len();
+    // don't let the two ends overlap on short sequences.
+    let window = window.min(len / 2);
 
-    let mut alignment = Alignment::new();
+    let left_arm = consensus[..window].to_ascii_uppercase();
+    let right_arm = reverse_complement(&consensus[len - window..]).to_ascii_uppercase();
+
+    let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
+    // gap open score: -5, gap extension score: -1
+    let mut aligner = Aligner::with_capacity(left_arm.len(), right_arm.len(), -5, -1, &score);
+    let alignment = aligner.local(&left_arm, &right_arm);
+
+    let (identity, mismatches, indels) = count_operations(&alignment.operations);
+    let length = alignment.xend - alignment.xstart;
+
+    Tir {
+        left_start: alignment.xstart,
+        left_end: alignment.xend,
+        // the right arm was reverse complemented, so flip back
+        // into forward consensus coordinates.
+        right_start: len - alignment.yend,
+        right_end: len - alignment.ystart,
+        length,
+        identity,
+        mismatches,
+        indels,
+        pass: length >= min_length && identity >= min_identity,
+        alignment,
+        left_arm,
+        right_arm,
+    }
+}
 
-    while let Some(Ok(record)) = reader.next() {
-        // alignment.push(std::str::from_utf8(record.seq()).unwrap().to_string());
-        alignment.add_sequence(Sequence {
-            name: record.id().to_string(),
-            sequence: record.seq().to_vec(),
-        });
-        read_number += 1;
+/// Returns the identity, number of mismatches, and number of
+/// indels in a set of pairwise alignment operations.
+pub fn count_operations(operations: &[AlignmentOperation]) -> (f64, usize, usize) {
+    let mut matches = 0;
+    let mut mismatches = 0;
+    let mut indels = 0;
+    for (op, _count) in SequentialCount::new(operations.iter()) {
+        if let AlignmentOperation::Ins | AlignmentOperation::Del = op {
+            // an indel is counted once per run of gaps.
+            indels += 1;
+        }
+    }
+    for op in operations {
+        match op {
+            AlignmentOperation::Match => matches += 1,
+            AlignmentOperation::Subst => mismatches += 1,
+            _ => (),
+        }
     }
 
-    let alignment_blocks = alignment.find_blocks(0.1, 0.8);
-    let alignment = alignment_blocks.trim(alignment, 15, 1, true);
+    let identity = if operations.is_empty() {
+        0.0
+    } else {
+        matches as f64 / operations.len() as f64
+    };
+    (identity, mismatches, indels)
+}
 
-    let profile = alignment.unwrap().get_profile();
-    let mut forward_consensus = get_consensus(profile, read_number);
+pub fn revcomp_alignment(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let show_alignment = matches.is_present("show");
+    let window = value_t!(matches.value_of("window"), usize).unwrap_or_else(|e| e.exit());
+    let min_length = value_t!(matches.value_of("min_length"), usize).unwrap_or_else(|e| e.exit());
+    let min_identity = value_t!(matches.value_of("min_identity"), f64).unwrap_or_else(|e| e.exit());
+    let params = ConsensusParams::from_matches(matches, "");
 
-    // remove all gaps as they mess up the alignment
-    forward_consensus.retain(|&e| e != 45);
+    // read in the alignment from file, and make the consensus
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let forward_consensus = trimmed_consensus(alignment, &params);
 
-    let reverse_consensus = reverse_complement(&forward_consensus);
+    let tir = find_tir(&forward_consensus, window, min_length, min_identity);
 
-    let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
-    // gap open score: -5, gap extension score: -1
-    let mut aligner = Aligner::with_capacity(
-        forward_consensus.len(),
-        reverse_consensus.len(),
-        -5,
-        -1,
-        &score,
+    println!(
+        "Left start\tLeft end\tRight start\tRight end\tLength\tIdentity\tMismatches\tIndels\tTIR"
+    );
+    println!(
+        "{}\t{}\t{}\t{}\t{}\t{:.3}\t{}\t{}\t{}",
+        tir.left_start + 1, // add one due to zero indexing.
+        tir.left_end,
+        tir.right_start + 1,
+        tir.right_end,
+        tir.length,
+        tir.identity,
+        tir.mismatches,
+        tir.indels,
+        if tir.pass { "PASS" } else { "FAIL" }
     );
-    let alignment = aligner.semiglobal(&forward_consensus, &reverse_consensus);
-    let alignment_pretty = alignment.pretty(&forward_consensus, &reverse_consensus);
-
-    let mut lim = 0;
-    let mut start = 0;
-    let mut end = 0;
-
-    for (base, count) in SequentialCount::new(alignment.operations.iter()) {
-        end += count;
-        if lim < 10 {
-            println!("{} - {}: {:?} occurs {} times", start, end, base, count);
-        } else {
-            break;
-        }
-        lim += 1;
-        start = end;
-    }
 
     if show_alignment {
-        print!("\n{}", alignment_pretty);
+        print!("\n{}", tir.pretty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 14 bp arm, then its reverse complement after some spacer.
+    fn element() -> Vec<u8> {
+        let arm = b"CAGGGTTGCAACTG";
+        let mut seq = arm.to_vec();
+        seq.extend(b"A".repeat(30));
+        seq.extend(reverse_complement(arm));
+        seq
+    }
+
+    #[test]
+    fn finds_the_arms() {
+        let seq = element();
+        let tir = find_tir(&seq, 20, 10, 0.8);
+        assert_eq!((tir.left_start, tir.left_end), (0, 14));
+        assert_eq!(
+            (tir.right_start, tir.right_end),
+            (seq.len() - 14, seq.len())
+        );
+        assert_eq!((tir.length, tir.mismatches, tir.indels), (14, 0, 0));
+        assert_eq!(tir.identity, 1.0);
+        assert!(tir.pass);
+        assert!(!find_tir(&seq, 20, 15, 0.8).pass);
+    }
+
+    #[test]
+    fn arms_do_not_overlap() {
+        let seq = b"ACGTTTTTTTAC";
+        let tir = find_tir(seq, 100, 1, 0.0);
+        assert_eq!(tir.left_arm.len(), seq.len() / 2);
+        assert!(tir.left_end <= tir.right_start);
+    }
+
+    #[test]
+    fn counts_operations() {
+        use AlignmentOperation::*;
+        let (identity, mismatches, indels) =
+            count_operations(&[Match, Match, Subst, Ins, Ins, Match, Del]);
+        assert_eq!((mismatches, indels), (1, 2));
+        assert!((identity - 3.0 / 7.0).abs() < 1e-9);
     }
 }
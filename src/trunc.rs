@@ -0,0 +1,298 @@
+use clap::value_t;
+use plotters::prelude::*;
+use std::fmt;
+
+use crate::utils::alignment::Alignment;
+use crate::utils::formats::{format_from_matches, read_alignment_as};
+use crate::utils::seqcount::most_common;
+
+// most copies of a LINE are 5' truncated, as reverse transcription
+// often stops early. where each copy starts and ends in the trimmed core
+// says how many full length copies there are, and where they tend to break.
+
+/// How much of the trimmed core a copy covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Truncation {
+    FullLength,
+    FivePrime,
+    ThreePrime,
+    Internal,
+}
+
+impl fmt::Display for Truncation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let truncation = match self {
+            Truncation::FullLength => "full-length",
+            Truncation::FivePrime => "5' truncated",
+            Truncation::ThreePrime => "3' truncated",
+            Truncation::Internal => "internal fragment",
+        };
+        write!(f, "{}", truncation)
+    }
+}
+
+/// Where a copy starts and ends in the trimmed core. Columns
+/// are zero based, half open, and in the whole alignment.
+#[derive(Debug, Clone)]
+pub struct CopyExtent {
+    pub start: usize,
+    pub end: usize,
+    pub truncation: Truncation,
+}
+
+/// Counts of each kind of copy, and where the 5' truncated copies
+/// most often start.
+#[derive(Debug, Clone)]
+pub struct TruncationSummary {
+    pub full_length: usize,
+    pub five_prime: usize,
+    pub three_prime: usize,
+    pub internal: usize,
+    /// copies which do not agree with the trimmed core anywhere.
+    pub absent: usize,
+    /// the histogram bin (start column, end column, half open) with
+    /// the most 5' truncated copies starting in it, and how many.
+    pub modal_truncation: Option<(usize, usize, usize)>,
+}
+
+// a copy starts (ends) at the first (last) run of `RUN` of its bases
+// where at least `RUN_MATCHES` are the most common base in their column.
+const RUN: usize = 10;
+const RUN_MATCHES: usize = 8;
+
+/// Where each copy starts and ends between the trim boundaries
+/// `trim_start` and `trim_end`. Copies starting within `slop` columns of
+/// `trim_start` are complete at the 5' end, and likewise for the 3' end.
+/// The flank of a truncated copy can be aligned anywhere upstream of where
+/// it really starts, so the ends are where the copy first (and last) agrees
+/// with the rest of the alignment. Copies which never do are `None`.
+pub fn copy_extents(
+    alignment: &Alignment,
+    trim_start: usize,
+    trim_end: usize,
+    slop: usize,
+) -> Vec<(String, Option<CopyExtent>)> {
+    // the most common base in each column of the core.
+    let majority: Vec<u8> = alignment.get_profile()[trim_start..trim_end]
+        .iter()
+        .map(|column| column.majority().map_or(b'-', |(base, _)| base))
+        .collect();
+
+    alignment
+        .matrix
+        .iter()
+        .map(|record| {
+            // the bases of this copy in the core, and whether each agrees.
+            let bases: Vec<(usize, bool)> = record.sequence[trim_start..trim_end]
+                .iter()
+                .zip(&majority)
+                .enumerate()
+                .filter(|(_, (&b, _))| b != b'-')
+                .map(|(i, (b, &m))| (trim_start + i, b.to_ascii_uppercase() == m))
+                .collect();
+            let agrees = |run: &[(usize, bool)]| {
+                run.iter().filter(|(_, m)| *m).count() >= RUN_MATCHES.min(run.len())
+            };
+            let runs = || bases.windows(RUN.min(bases.len()).max(1));
+            let first = runs().find(|run| agrees(run));
+            let last = runs().rev().find(|run| agrees(run));
+
+            let extent = first.zip(last).map(|(first, last)| {
+                // start and end on a matching base.
+                let start = first.iter().find(|(_, m)| *m).unwrap().0;
+                let end = last.iter().rev().find(|(_, m)| *m).unwrap().0 + 1;
+                let five = start <= trim_start + slop;
+                let three = end + slop >= trim_end;
+                let truncation = match (five, three) {
+                    (true, true) => Truncation::FullLength,
+                    (false, true) => Truncation::FivePrime,
+                    (true, false) => Truncation::ThreePrime,
+                    (false, false) => Truncation::Internal,
+                };
+                CopyExtent {
+                    start,
+                    end,
+                    truncation,
+                }
+            });
+            (record.name.clone(), extent)
+        })
+        .collect()
+}
+
+/// Count the positions falling into each `bin` columns wide bin,
+/// from `trim_start` to `trim_end`.
+pub fn histogram(
+    positions: impl Iterator<Item = usize>,
+    trim_start: usize,
+    trim_end: usize,
+    bin: usize,
+) -> Vec<usize> {
+    let bin = bin.max(1);
+    let mut counts = vec![0; trim_end.saturating_sub(trim_start).div_ceil(bin).max(1)];
+    for position in positions {
+        let index = (position.saturating_sub(trim_start) / bin).min(counts.len() - 1);
+        counts[index] += 1;
+    }
+    counts
+}
+
+/// Summarise the copy extents. The modal truncation point is found
+/// in bins of `bin` columns.
+pub fn summarise_truncation(
+    extents: &[(String, Option<CopyExtent>)],
+    trim_start: usize,
+    trim_end: usize,
+    bin: usize,
+) -> TruncationSummary {
+    let count = |truncation: Truncation| {
+        extents
+            .iter()
+            .filter(|(_, e)| e.as_ref().is_some_and(|e| e.truncation == truncation))
+            .count()
+    };
+    let bin = bin.max(1);
+    let modal_truncation = most_common(
+        extents
+            .iter()
+            .filter_map(|(_, e)| e.as_ref())
+            .filter(|e| e.truncation == Truncation::FivePrime)
+            .map(|e| (e.start - trim_start) / bin),
+    )
+    .map(|(index, copies)| {
+        let start = trim_start + index * bin;
+        (start, (start + bin).min(trim_end), copies)
+    });
+
+    TruncationSummary {
+        full_length: count(Truncation::FullLength),
+        five_prime: count(Truncation::FivePrime),
+        three_prime: count(Truncation::ThreePrime),
+        internal: count(Truncation::Internal),
+        absent: extents.iter().filter(|(_, e)| e.is_none()).count(),
+        modal_truncation,
+    }
+}
+
+pub fn trunc(matches: &clap::ArgMatches) {
+    let fasta = matches.value_of("fasta").unwrap();
+    let extend = value_t!(matches.value_of("extend"), usize).unwrap_or_else(|e| e.exit());
+    let next_hit = value_t!(matches.value_of("next_hit"), usize).unwrap_or_else(|e| e.exit());
+    let min_occupancy =
+        value_t!(matches.value_of("min_occupancy"), f64).unwrap_or_else(|e| e.exit());
+    let iden = value_t!(matches.value_of("identity"), f64).unwrap_or_else(|e| e.exit());
+    let slop = value_t!(matches.value_of("slop"), usize).unwrap_or_else(|e| e.exit());
+    let bin = value_t!(matches.value_of("bin"), usize).unwrap_or_else(|e| e.exit());
+    let plot = matches.is_present("plot");
+    let dir = value_t!(matches.value_of("dir"), String).unwrap_or_else(|e| e.exit());
+    let name = value_t!(matches.value_of("name"), String).unwrap_or_else(|e| e.exit());
+
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
+    let (trim_start, trim_end) = alignment
+        .find_blocks_gap_aware(min_occupancy, iden)
+        .trim_bounds(alignment.matrix[0].len(), extend, next_hit);
+    let extents = copy_extents(&alignment, trim_start, trim_end, slop);
+    let summary = summarise_truncation(&extents, trim_start, trim_end, bin);
+
+    // positions are given relative to the start of the core, and in the alignment.
+    println!("Sequence\tCore start\tCore end\tColumn start\tColumn end\tCopy");
+    for (name, extent) in &extents {
+        match extent {
+            Some(e) => println!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                name,
+                e.start - trim_start + 1, // add one due to zero indexing.
+                e.end - trim_start,
+                e.start + 1,
+                e.end,
+                e.truncation
+            ),
+            None => println!("{}\t-\t-\t-\t-\tabsent", name),
+        }
+    }
+
+    eprintln!(
+        "[+]\tCore is columns {}-{}. {} full-length, {} 5' truncated, {} 3' truncated, {} internal fragments, {} absent.",
+        trim_start + 1,
+        trim_end,
+        summary.full_length,
+        summary.five_prime,
+        summary.three_prime,
+        summary.internal,
+        summary.absent
+    );
+    if let Some((start, end, copies)) = summary.modal_truncation {
+        eprintln!(
+            "[+]\tModal 5' truncation point is core position {}-{} ({} copies).",
+            start - trim_start + 1,
+            end - trim_start,
+            copies
+        );
+    }
+
+    if plot {
+        let copies: Vec<&CopyExtent> = extents.iter().filter_map(|(_, e)| e.as_ref()).collect();
+        let starts = histogram(copies.iter().map(|e| e.start), trim_start, trim_end, bin);
+        let ends = histogram(copies.iter().map(|e| e.end - 1), trim_start, trim_end, bin);
+        plot_extents(&starts, &ends, bin, &dir, &name).expect("Couldn't make the plot :(");
+    }
+}
+
+fn plot_extents(
+    starts: &[usize],
+    ends: &[usize],
+    bin: usize,
+    dir: &str,
+    name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // dimensions of the plot
+    let dims = (1280, 2 * 480);
+    let xmax = starts.len() * bin;
+    let ymax = starts.iter().chain(ends).max().copied().unwrap_or(0) + 1;
+
+    let path = format!("{}/{}.png", dir, name);
+
+    let root = BitMapBackend::new(&path, (dims.0, dims.1)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let root = root.margin(10, 10, 10, 10);
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(20)
+        .y_label_area_size(40)
+        .set_label_area_size(LabelAreaPosition::Left, (8).percent())
+        .set_label_area_size(LabelAreaPosition::Bottom, (8).percent())
+        .build_cartesian_2d(0usize..xmax, 0usize..ymax)?;
+
+    chart
+        .configure_mesh()
+        .y_desc("Copies")
+        .x_desc("Position along the trimmed core")
+        .label_style(TextStyle::from(("sans-serif", 25)))
+        .draw()?;
+
+    // starts on the left half of each bin, ends on the right.
+    let half = (bin / 2).max(1);
+    chart
+        .draw_series(
+            starts
+                .iter()
+                .enumerate()
+                .map(|(i, &n)| Rectangle::new([(i * bin, 0), (i * bin + half, n)], BLUE.filled())),
+        )?
+        .label("Copy starts")
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], BLUE.filled()));
+    chart
+        .draw_series(ends.iter().enumerate().map(|(i, &n)| {
+            Rectangle::new([(i * bin + half, 0), ((i + 1) * bin, n)], RED.filled())
+        }))?
+        .label("Copy ends")
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], RED.filled()));
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE)
+        .border_style(BLACK)
+        .label_font(("sans-serif", 25))
+        .draw()?;
+
+    Ok(())
+}
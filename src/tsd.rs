@@ -2,9 +2,9 @@
 
 // TODO: add options for length of each end looked at
 
-use crate::utils::alignment::{Alignment, Sequence};
-use bio::io::fasta;
+use crate::utils::formats::{format_from_matches, read_alignment_as};
 use clap::value_t;
+use std::collections::HashMap;
 
 pub fn find_tsds(matches: &clap::ArgMatches) {
     // for each sequence
@@ -20,19 +20,24 @@ pub fn find_tsds(matches: &clap::ArgMatches) {
     let min_window = value_t!(matches.value_of("minimum"), usize).unwrap_or_else(|e| e.exit());
     let max_window = value_t!(matches.value_of("maximum"), usize).unwrap_or_else(|e| e.exit());
 
-    let reader = fasta::Reader::from_file(fasta).expect("[-]\tPath invalid.");
-
-    let mut alignment = Alignment::new();
-    for record in reader.records() {
-        let record = record.expect("[-]\tError during fasta record parsing.");
-
-        alignment.add_sequence(Sequence {
-            name: record.id().to_string(),
-            sequence: record.seq().to_vec(),
-        })
-    }
+    let alignment = read_alignment_as(fasta, format_from_matches(matches));
     // prints a table
     alignment
         .to_tsd_hash(length, min_window, max_window)
         .merge(false);
 }
+
+/// The TSD found in the most sequences, and how many, from the output
+/// of `TSDHash::merge`. Ties are broken by the longer, then alphabetically
+/// first TSD.
+pub fn top_tsd(tsds: &HashMap<String, Vec<String>>) -> Option<(String, usize)> {
+    let mut tsd_counts: HashMap<String, usize> = HashMap::new();
+    for tsd in tsds.values().flatten() {
+        *tsd_counts.entry(tsd.to_ascii_uppercase()).or_insert(0) += 1;
+    }
+    tsd_counts.into_iter().max_by(|a, b| {
+        a.1.cmp(&b.1)
+            .then(a.0.len().cmp(&b.0.len()))
+            .then(b.0.cmp(&a.0))
+    })
+}
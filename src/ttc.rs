@@ -2,10 +2,15 @@
 // trim to core sequence?
 // time to cry?
 
-use bio::io::fasta;
 use clap::value_t;
+use std::fs::File;
+use std::io;
+use std::path::Path;
 
-use crate::utils::alignment::{Alignment, Sequence};
+use crate::con::{get_consensus, ConsensusParams};
+use crate::utils::alignment::Alignment;
+use crate::utils::formats::{format_from_matches, read_alignment_as, write_stockholm};
+use crate::utils::locus::{locus_regex, write_bed, write_gff3, Locus};
 
 pub fn ttc(matches: &clap::ArgMatches) {
     let fasta = matches.value_of("fasta").unwrap();
@@ -13,20 +18,106 @@ pub fn ttc(matches: &clap::ArgMatches) {
     let next_hit = value_t!(matches.value_of("next_hit"), usize).unwrap_or_else(|e| e.exit());
     let miss = value_t!(matches.value_of("missing"), f64).unwrap_or_else(|e| e.exit());
     let iden = value_t!(matches.value_of("identity"), f64).unwrap_or_else(|e| e.exit());
+    let stockholm = matches.is_present("stockholm");
 
-    let reader = fasta::Reader::from_file(fasta).expect("[-]\tPath invalid.");
-
-    // read the fasta into our struct
-    let mut matrix = Alignment::new();
-    for record in reader.records() {
-        let fasta_record = record.expect("[-]\tError during fasta record parsing.");
-        matrix.add_sequence(Sequence {
-            name: fasta_record.id().to_string(),
-            sequence: fasta_record.seq().to_vec(),
-        })
+    // read the alignment into our struct
+    let mut matrix = read_alignment_as(fasta, format_from_matches(matches));
+    if let Some(regex) = matches.value_of("locus_regex") {
+        matrix.set_loci(&locus_regex(regex));
     }
 
+    // the family name, for the Stockholm ID and GFF3 Name.
+    let id = match matches.value_of("id") {
+        Some(id) => id.to_string(),
+        None => matrix
+            .gf
+            .iter()
+            .find(|(tag, _)| tag == "ID")
+            .map(|(_, text)| text.clone())
+            .unwrap_or_else(|| {
+                Path::new(fasta)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("TE")
+                    .to_string()
+            }),
+    };
+
     // find the blocks and trim
     let blocks = matrix.find_blocks(miss, iden);
-    blocks.trim(matrix, extend, next_hit, false);
+
+    // genomic coordinates of the trimmed copies.
+    let (bed, gff3) = (matches.value_of("bed"), matches.value_of("gff3"));
+    if bed.is_some() || gff3.is_some() {
+        let trimmed = blocks.trim(matrix.clone(), extend, next_hit, true).unwrap();
+        write_loci(&trimmed, bed, gff3, &id);
+    }
+
+    if !stockholm {
+        blocks.trim(matrix, extend, next_hit, false);
+        return;
+    }
+
+    //
+    // Dfam style seed alignment:
+    // trimmed alignment, with trimmed coordinates in the names,
+    // and the consensus as the RF line.
+    //
+    let params = ConsensusParams::from_matches(matches, "");
+    // headers carrying coordinates are rewritten as those of the trimmed
+    // copy, as in Dfam seeds. Other headers are left alone.
+    let mut trimmed = blocks.trim(matrix.clone(), extend, next_hit, true).unwrap();
+    for seq in trimmed.matrix.iter_mut() {
+        if let Some(locus) = &seq.locus {
+            seq.name = locus.seed_name();
+        }
+    }
+    // the #=GS lines refer to the old names, which have now changed.
+    trimmed.gs.clear();
+
+    let consensus = get_consensus(&trimmed.get_profile(), trimmed.matrix.len() as i32, &params);
+
+    // TP overrides any the input already had, as does ID.
+    let tp = matches.value_of("type").unwrap();
+    let mut gf = vec![("ID".to_string(), id), ("TP".to_string(), tp.to_string())];
+    gf.extend(
+        matrix
+            .gf
+            .iter()
+            .filter(|(tag, _)| tag != "ID" && tag != "TP")
+            .cloned(),
+    );
+
+    write_stockholm(&mut io::stdout(), &trimmed, &gf, &consensus)
+        .expect("[-]\tCould not write Stockholm alignment.");
+}
+
+// write the loci of the trimmed copies to BED and/or GFF3 files,
+// named by their original headers.
+fn write_loci(trimmed: &Alignment, bed: Option<&str>, gff3: Option<&str>, family: &str) {
+    let loci: Vec<(&str, &Locus)> = trimmed
+        .matrix
+        .iter()
+        .filter_map(|seq| Some((seq.name.as_str(), seq.locus.as_ref()?)))
+        // copies trimmed away entirely.
+        .filter(|(_, locus)| !locus.is_empty())
+        .collect();
+    let missing = trimmed.matrix.len() - loci.len();
+    if missing > 0 {
+        eprintln!(
+            "[-]\t{} sequences have no coordinates in their headers, or nothing left after trimming. Skipping them.",
+            missing
+        );
+    }
+
+    if let Some(path) = bed {
+        let mut file = File::create(path).expect("[-]\tCould not create BED file.");
+        write_bed(&mut file, &loci).expect("[-]\tCould not write BED file.");
+        eprintln!("[+]\t{} trimmed copies written to {}.", loci.len(), path);
+    }
+    if let Some(path) = gff3 {
+        let mut file = File::create(path).expect("[-]\tCould not create GFF3 file.");
+        write_gff3(&mut file, &loci, family).expect("[-]\tCould not write GFF3 file.");
+        eprintln!("[+]\t{} trimmed copies written to {}.", loci.len(), path);
+    }
 }
@@ -1,5 +1,8 @@
 /// Module for handling alignments in fasta format
-use crate::utils::blocks::{BlockRecord, BlockRecords};
+use crate::utils::blocks::BlockRecords;
+use crate::utils::locus::Locus;
+use crate::utils::profile::{DiversityWindow, Profile, SiteStats};
+use regex::Regex;
 use std::collections::HashMap;
 use std::fmt;
 
@@ -7,10 +10,12 @@ use std::fmt;
 /// encoding the sequences as a Vec<u8>
 /// and the headers as Strings.
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Sequence {
     pub name: String,
     pub sequence: Vec<u8>,
+    /// where the sequence is in the genome, if the header says.
+    pub locus: Option<Locus>,
 }
 
 impl Sequence {
@@ -25,14 +30,22 @@ impl Sequence {
 
 /// Alignment loads all the Sequences
 /// in the fasta file into memory
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct Alignment {
     pub matrix: Vec<Sequence>,
+    /// Stockholm `#=GF` (feature, text) lines, if read from Stockholm.
+    pub gf: Vec<(String, String)>,
+    /// Stockholm `#=GS` (sequence name, feature, text) lines.
+    pub gs: Vec<(String, String, String)>,
 }
 
 impl Alignment {
     pub fn new() -> Self {
-        Alignment { matrix: Vec::new() }
+        Alignment {
+            matrix: Vec::new(),
+            gf: Vec::new(),
+            gs: Vec::new(),
+        }
     }
     // add a sequence to the alignment in memory
     pub fn add_sequence(&mut self, seq: Sequence) {
@@ -43,156 +56,76 @@ impl Alignment {
         self.matrix.clear()
     }
 
-    pub fn find_blocks(&self, miss: f64, iden: f64) -> BlockRecords {
-        let t = Self::transpose(self);
-
-        let mut blocks: BlockRecords = BlockRecords(Vec::new());
-        for (position, column) in t.iter().enumerate() {
-            let col_len = column.len();
-            // calculate identity & missing
-            let mut table = HashMap::new();
-            for base in column {
-                *table.entry(base).or_insert(0) += 1;
-            }
-            // percent identical. bad error handling here.
-            let identity = table
-                .iter()
-                .max_by(|a, b| a.1.cmp(b.1))
-                .unwrap_or((&&0u8, &0i32));
-            let per_identity = {
-                if identity.0 == &&45u8 {
-                    0.0
-                } else {
-                    *identity.1 as f64 / col_len as f64
-                }
-            };
-            let per_missing = *table.get(&45u8).unwrap_or(&0) as f64 / col_len as f64;
-
-            // add a block IF
-            // missing (dashes) below 0.1 AND column should be 0.9 identical.
-            if per_missing < miss && per_identity > iden {
-                blocks.add_record(BlockRecord {
-                    position,
-                    identity: per_identity,
-                    missing: per_missing,
-                })
-            }
+    /// Parse the loci from the sequence names with `regex` (see
+    /// `Locus::parse_with`), replacing any parsed when reading.
+    pub fn set_loci(&mut self, regex: &Regex) {
+        for seq in self.matrix.iter_mut() {
+            seq.locus = Locus::parse_with(&seq.name, regex);
         }
-        // println!("{}", blocks);
-        blocks
     }
 
-    // transpose the alignment
-    fn transpose(&self) -> Vec<Vec<u8>> {
-        let v = &self.matrix;
-        assert!(!v.is_empty());
-        (0..v[0].len())
-            .map(|i| v.iter().map(|inner| inner.sequence[i]).collect::<Vec<u8>>())
-            .collect()
+    /// Columns with few gaps, where most sequences agree. See
+    /// `Profile::find_blocks`.
+    pub fn find_blocks(&self, miss: f64, iden: f64) -> BlockRecords {
+        self.get_profile().find_blocks(miss, iden)
+    }
+
+    /// See `Profile::find_blocks_gap_aware`.
+    pub fn find_blocks_gap_aware(&self, min_occupancy: f64, iden: f64) -> BlockRecords {
+        self.get_profile()
+            .find_blocks_gap_aware(min_occupancy, iden)
+    }
+
+    /// Are all the sequences the same length, as they should be in
+    /// an alignment?
+    pub fn is_aligned(&self) -> bool {
+        self.matrix
+            .windows(2)
+            .all(|pair| pair[0].sequence.len() == pair[1].sequence.len())
+    }
+
+    /// The number of sequences which are not a gap in each column.
+    pub fn coverage(&self) -> Vec<usize> {
+        self.get_profile().coverage()
     }
 
+    /// Nucleotide diversity in windows of the alignment. See
+    /// `Profile::div_windows`.
     pub fn div_windows(
         &self,
         window_size: usize,
         window_step: usize,
-        internal: bool,
-    ) -> Vec<(usize, usize, f32)> {
-        // let mut window = Vec::<String>::new(); // vector to store window
-        let mut window = Alignment::new();
-        let mut start: usize = 0; // index to start the window
-        let mut end: usize = start + window_size; // index to end the window
-
-        let mut data = Vec::new();
-
-        while end <= self.matrix[0].len() {
-            for row in &self.matrix {
-                let seq = &row.sequence[start..end];
-                window.add_sequence(Sequence {
-                    name: row.name.clone(),
-                    sequence: seq.to_vec(),
-                });
-            }
-
-            let pi = window.calculate_pi();
-            // window contains a vec of chunks.
-            if !internal {
-                println!("{}\t{}\t{:.3}", start, end, pi);
-            }
-            data.push((start, end, pi));
-
-            start += window_step;
-            end += window_step;
-
-            window.clear();
-        }
-
-        data
+        min_sites: f32,
+    ) -> Vec<DiversityWindow> {
+        self.get_profile()
+            .div_windows(window_size, window_step, min_sites)
     }
 
-    // heavily poached from https://github.com/noahaus/sliding-window-scripts/blob/13d872b379a3501ca9b506f8bcccf89a9cd81c8d/tjd/src/main.rs
-
+    /// Mean number of differences between pairs of sequences.
     pub fn calculate_pi(&self) -> f32 {
-        // number of sequences being analyzed
-        let align_length = self.matrix.len();
-        // vector that holds all the values of the pairwise distances
-        let mut distances = Vec::new();
-
-        // calculate hamming distance between two sequences at a time
-        for i in 0..align_length {
-            for j in 0..align_length {
-                if i < j {
-                    distances.push(Self::hamming_distance(
-                        &self.matrix[i].sequence,
-                        &self.matrix[j].sequence,
-                    ));
-                } else {
-                    continue;
-                }
-            }
-        }
-
-        // sum the pairwise distances and then divide by n(n-1)
-        let n = align_length as i32;
-        let dist_sum = distances.iter().sum::<i32>();
-        let np = ((n * (n - 1)) as f32) / 2.0;
-        dist_sum as f32 / np
+        self.get_profile().calculate_pi()
     }
 
-    // 2 given two string sequences, output the hamming distance between them
-    // ignore base pair comparisons where there is one or more dash, or N.
-
-    fn hamming_distance(seq1: &[u8], seq2: &[u8]) -> i32 {
-        let mut distance = 0i32;
+    /// Differences per site compared, over all pairs.
+    pub fn calculate_pi_per_site(&self) -> Option<f32> {
+        self.get_profile().calculate_pi_per_site()
+    }
 
-        for (b1, b2) in seq1.iter().zip(seq2.iter()) {
-            if b1 != b2 && *b1 != 45u8 && *b2 != 45u8 && *b1 != 78u8 && *b2 != 78u8 {
-                distance += 1;
-            } else {
-                continue;
-            }
-        }
-        distance
+    /// Differences and sites compared, summed over all pairs of
+    /// sequences, and the number of pairs.
+    pub fn pairwise_differences(&self) -> (i32, i32, usize) {
+        self.get_profile().pairwise_differences()
     }
 
-    // Get frequencies of each nucleotide at each position in an alignment (profile)
+    /// Segregating sites, Watterson's theta and Tajima's D.
+    pub fn site_stats(&self) -> SiteStats {
+        self.get_profile().site_stats()
+    }
 
-    pub fn get_profile(&self) -> Vec<HashMap<u8, usize>> {
-        // as all sequence lengths should be the same
-        let sequence_length = self.matrix[0].sequence.len();
-        let mut profile = Vec::with_capacity(sequence_length);
-        for i in 0..sequence_length {
-            let position_string = self
-                .matrix
-                .iter()
-                .map(|record| *record.sequence.get(i).unwrap())
-                .collect::<Vec<u8>>();
-            let mut profile_hash = HashMap::new();
-            for base in position_string {
-                *profile_hash.entry(base).or_insert(0) += 1;
-            }
-            profile.push(profile_hash);
-        }
-        profile
+    /// The counts of each base in each column (the profile), in one
+    /// pass over the sequences.
+    pub fn get_profile(&self) -> Profile {
+        Profile::new(&self.matrix)
     }
 
     pub fn to_tsd_hash(&self, length: usize, min_window: usize, max_window: usize) -> TSDHash {
@@ -246,6 +179,7 @@ pub struct AlignHash {
     pub right: HashMap<Vec<u8>, usize>,
 }
 
+#[derive(Default)]
 pub struct TSDHash(Vec<AlignHash>);
 
 impl TSDHash {
@@ -30,15 +30,15 @@ impl BlockRecords {
         self.0.push(value)
     }
 
-    // add internal switch, so we can use the inner API for html
-    pub fn trim(
-        &self,
-        alignment: Alignment,
-        extend: usize,
-        next_hit: usize,
-        internal: bool,
-    ) -> Option<Alignment> {
-        let removed_isolates = Self::remove_isolates(self, next_hit);
+    /// The columns, as a half open range, that an alignment of
+    /// `length` columns is trimmed to by `trim`.
+    pub fn trim_bounds(&self, length: usize, extend: usize, next_hit: usize) -> (usize, usize) {
+        // guard against there being no blocks at all.
+        let removed_isolates = if self.0.is_empty() {
+            BlockRecords(Vec::new())
+        } else {
+            Self::remove_isolates(self, next_hit)
+        };
         let start: usize;
         let end: usize;
 
@@ -48,7 +48,7 @@ impl BlockRecords {
                 // start at the beginning
                 start = 0;
                 // assume the end of the alignment is the end.
-                end = alignment.matrix[0].len();
+                end = length;
             }
             false => {
                 start = removed_isolates.0[0].position;
@@ -56,34 +56,49 @@ impl BlockRecords {
             }
         }
 
-        // if we want to render a html doc.
-        let mut _internal_alignment = Alignment::new();
+        // calculate new starts and ends
+        let new_start = start.saturating_sub(extend);
 
-        for seq in alignment.matrix {
-            // more guarding needed here
-            let seq_length = seq.sequence.len();
-            // calculate new starts and ends
+        let new_end = if end + extend >= length {
+            length
+        } else {
+            end + extend
+        };
 
-            let new_start = if start - extend == 0 {
-                0
-            } else {
-                start - extend
-            };
+        (new_start, new_end)
+    }
 
-            let new_end = if end + extend >= seq_length {
-                seq_length
-            } else {
-                end + extend
-            };
+    // add internal switch, so we can use the inner API for html
+    pub fn trim(
+        &self,
+        alignment: Alignment,
+        extend: usize,
+        next_hit: usize,
+        internal: bool,
+    ) -> Option<Alignment> {
+        let (new_start, new_end) = self.trim_bounds(alignment.matrix[0].len(), extend, next_hit);
 
+        // if we want to render a html doc.
+        let mut _internal_alignment = Alignment::new();
+
+        for seq in alignment.matrix {
             let trimmed_seq = seq.sequence.get(new_start..new_end);
 
             if internal {
                 // push to internal parseable struct
+                // bases (not columns) lost from either end of this copy.
+                let non_gap = |s: &[u8]| s.iter().filter(|&&b| b != b'-').count();
+                let locus = seq.locus.as_ref().map(|l| {
+                    l.trim(
+                        seq.sequence.get(..new_start).map_or(0, non_gap),
+                        seq.sequence.get(new_end..).map_or(0, non_gap),
+                    )
+                });
                 match trimmed_seq {
                     Some(t) => _internal_alignment.add_sequence(Sequence {
                         name: seq.name,
                         sequence: t.to_vec(),
+                        locus,
                     }),
                     None => {
                         eprintln!("[-]\tHmm, shouldn't ever reach this message. Report as bug!")
@@ -143,15 +158,15 @@ impl BlockRecords {
                             // if the position of the current hit
                             // minus/plus 10bp is not greater/less (i.e. it includes)
                             // than the previous/next hit, add this hit.
-                            if !(val.position - next_hit > lv.position)
-                                && !(val.position + next_hit < rv.position)
+                            if val.position.saturating_sub(next_hit) <= lv.position
+                                && val.position + next_hit >= rv.position
                             {
                                 out_vec.push(val)
                             }
                         }
                         None => {
                             // lv only exists
-                            if !(val.position - next_hit > lv.position) {
+                            if val.position.saturating_sub(next_hit) <= lv.position {
                                 out_vec.push(val)
                             }
                         }
@@ -161,7 +176,7 @@ impl BlockRecords {
                     match right_val {
                         Some(rv) => {
                             // rv only exists
-                            if !(val.position + next_hit < rv.position) {
+                            if val.position + next_hit >= rv.position {
                                 out_vec.push(val)
                             }
                         }
@@ -0,0 +1,73 @@
+// the flanks of an element in each aligned sequence, and the target site
+// duplications (TSD's) at its boundaries, for the commands which find
+// the element in the consensus first (classify, helitron).
+
+use crate::utils::alignment::Alignment;
+
+/// The TSD either side of the element in each sequence, if any: the
+/// longest run of `min_window..=max_window` bases just before the element
+/// which is repeated just after it. `columns` maps the consensus to the
+/// alignment, and `element` is in consensus coordinates. Palindromic TSD's
+/// (TA, TTAA) can be swallowed by a TIR, so the boundaries are also tried
+/// up to `inside` bases inside the element.
+pub fn boundary_tsds(
+    alignment: &Alignment,
+    columns: &[usize],
+    element: (usize, usize),
+    min_window: usize,
+    max_window: usize,
+    inside: usize,
+) -> Vec<Option<String>> {
+    let (start, end) = element;
+
+    alignment
+        .matrix
+        .iter()
+        .map(|record| {
+            // the longest TSD, then the boundary nearest the element edge.
+            let mut best: Option<Vec<u8>> = None;
+            for inside in 0..=inside.min((end - start) / 2) {
+                let (left, right) =
+                    element_flanks(&record.sequence, columns, (start + inside, end - inside));
+                let found = (min_window..=max_window).rev().find(|&k| {
+                    k <= left.len() && k <= right.len() && left[left.len() - k..] == right[..k]
+                });
+                if let Some(k) = found {
+                    if best.as_ref().map_or(true, |b| k > b.len()) {
+                        best = Some(right[..k].to_vec());
+                    }
+                }
+            }
+            best.map(|tsd| String::from_utf8_lossy(&tsd).to_string())
+        })
+        .collect()
+}
+
+/// The bases (upper case, no gaps) of an aligned sequence before and
+/// after the element, which is in consensus coordinates. Empty if the
+/// element is not in the consensus, e.g. when trimming left nothing.
+pub fn element_flanks(
+    sequence: &[u8],
+    columns: &[usize],
+    element: (usize, usize),
+) -> (Vec<u8>, Vec<u8>) {
+    let (start, end) = element;
+    let bases = |part: &[u8]| -> Vec<u8> {
+        part.iter()
+            .filter(|&&b| b != b'-')
+            .map(|b| b.to_ascii_uppercase())
+            .collect()
+    };
+    // no candidate element, so no flanks.
+    let (first, last) = match (
+        columns.get(start),
+        end.checked_sub(1).and_then(|e| columns.get(e)),
+    ) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return (Vec::new(), Vec::new()),
+    };
+    (
+        bases(sequence.get(..first).unwrap_or_default()),
+        bases(sequence.get(last + 1..).unwrap_or_default()),
+    )
+}
@@ -0,0 +1,549 @@
+/// Module for reading alignments in the formats TE libraries
+/// and aligners tend to produce. Everything ends up as an
+/// `Alignment`, with gaps as dashes.
+use crate::utils::alignment::{Alignment, Sequence};
+use crate::utils::locus::{default_regex, Locus};
+use clap::Arg;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The alignment formats which can be read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Fasta,
+    Stockholm,
+    Clustal,
+    Phylip,
+    /// Fasta with lower case insert states and `.` gaps.
+    A2m,
+    /// As A2M, but gaps in insert columns are left out.
+    A3m,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fasta" => Ok(Format::Fasta),
+            "stockholm" => Ok(Format::Stockholm),
+            "clustal" => Ok(Format::Clustal),
+            "phylip" => Ok(Format::Phylip),
+            "a2m" => Ok(Format::A2m),
+            "a3m" => Ok(Format::A3m),
+            _ => Err(format!("{} is not a supported alignment format.", s)),
+        }
+    }
+}
+
+/// Guess the format of an alignment from its contents.
+pub fn detect_format(contents: &str) -> Format {
+    let first = contents
+        .lines()
+        .map(|l| l.trim())
+        .find(|l| !l.is_empty())
+        .unwrap_or("");
+
+    if first.starts_with("# STOCKHOLM") {
+        Format::Stockholm
+    } else if first.starts_with("CLUSTAL") || first.starts_with("MUSCLE") {
+        Format::Clustal
+    } else if first.starts_with('>') {
+        // a2m and a3m are fasta, with lower case inserts.
+        let records = parse_fasta(contents);
+        let same_length = records.windows(2).all(|w| w[0].1.len() == w[1].1.len());
+        let any = |f: fn(&u8) -> bool| records.iter().any(|(_, s)| s.iter().any(f));
+        if !same_length && is_a3m(&records) {
+            Format::A3m
+        } else if same_length && any(|b| *b == b'.') {
+            Format::A2m
+        } else {
+            Format::Fasta
+        }
+    } else if first
+        .split_whitespace()
+        .take(2)
+        .filter(|n| n.parse::<usize>().is_ok())
+        .count()
+        == 2
+    {
+        Format::Phylip
+    } else {
+        Format::Fasta
+    }
+}
+
+// unaligned fasta which is soft masked also has lower case and rows of
+// different lengths, so only call it a3m if the rows have gaps, and
+// they all have the same number of match states (upper case or gaps).
+fn is_a3m(records: &[(String, Vec<u8>)]) -> bool {
+    let match_states = |s: &[u8]| {
+        s.iter()
+            .filter(|b| b.is_ascii_uppercase() || **b == b'-')
+            .count()
+    };
+    let any = |f: fn(&u8) -> bool| records.iter().any(|(_, s)| s.iter().any(f));
+    any(u8::is_ascii_lowercase)
+        && any(|b| *b == b'-' || *b == b'.')
+        && records.first().is_some_and(|(_, s)| match_states(s) > 0)
+        && records
+            .windows(2)
+            .all(|w| match_states(&w[0].1) == match_states(&w[1].1))
+}
+
+/// Read an alignment from file, detecting the format.
+pub fn read_alignment<P: AsRef<Path>>(path: P) -> Alignment {
+    read_alignment_as(path, None)
+}
+
+/// Read an alignment from file in the given format, or detect it
+/// if there is none. Exits if the file can't be read or parsed.
+pub fn read_alignment_as<P: AsRef<Path>>(path: P, format: Option<Format>) -> Alignment {
+    try_read_alignment_as(path, format).unwrap_or_else(|e| {
+        eprintln!("[-]\t{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// As `read_alignment_as`, but returns an error instead of exiting.
+pub fn try_read_alignment_as<P: AsRef<Path>>(
+    path: P,
+    format: Option<Format>,
+) -> Result<Alignment, String> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}.", path.display(), e))?;
+    let format = format.unwrap_or_else(|| detect_format(&contents));
+    parse_alignment(&contents, format)
+}
+
+/// The `--input_format` option, for when the format is guessed wrong.
+pub fn input_format_arg() -> Arg<'static, 'static> {
+    Arg::with_name("input_format")
+        .long("input_format")
+        .takes_value(true)
+        .default_value("auto")
+        .possible_values(&[
+            "auto",
+            "fasta",
+            "stockholm",
+            "clustal",
+            "phylip",
+            "a2m",
+            "a3m",
+        ])
+        .help("Format of the alignment, detected from the contents if auto.")
+}
+
+/// The format given with `--input_format`, or `None` to detect it.
+pub fn format_from_matches(matches: &clap::ArgMatches) -> Option<Format> {
+    match matches.value_of("input_format") {
+        None | Some("auto") => None,
+        Some(format) => Some(format.parse().unwrap_or_else(|e: String| {
+            eprintln!("[-]\t{}", e);
+            std::process::exit(1);
+        })),
+    }
+}
+
+/// Parse an alignment held in a string.
+pub fn parse_alignment(contents: &str, format: Format) -> Result<Alignment, String> {
+    let mut alignment = match format {
+        Format::Fasta => from_records(parse_fasta(contents)),
+        Format::Stockholm => parse_stockholm(contents),
+        Format::Clustal => from_records(parse_clustal(contents)),
+        Format::Phylip => from_records(parse_phylip(contents)?),
+        Format::A2m => from_records(parse_fasta(contents)),
+        Format::A3m => from_records(expand_a3m(parse_fasta(contents))),
+    };
+    // everything uses dashes for gaps.
+    for seq in alignment.matrix.iter_mut() {
+        for base in seq.sequence.iter_mut() {
+            if *base == b'.' {
+                *base = b'-';
+            }
+        }
+    }
+    Ok(alignment)
+}
+
+fn from_records(records: Vec<(String, Vec<u8>)>) -> Alignment {
+    let mut alignment = Alignment::new();
+    let regex = default_regex();
+    for (name, sequence) in records {
+        let locus = Locus::parse_with(&name, &regex);
+        alignment.add_sequence(Sequence {
+            name,
+            sequence,
+            locus,
+        });
+    }
+    alignment
+}
+
+// the id is the header up to the first whitespace, as in bio::io::fasta.
+fn parse_fasta(contents: &str) -> Vec<(String, Vec<u8>)> {
+    let mut records: Vec<(String, Vec<u8>)> = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('>') {
+            let id = header.split_whitespace().next().unwrap_or("").to_string();
+            records.push((id, Vec::new()));
+        } else if let Some(last) = records.last_mut() {
+            last.1
+                .extend(line.bytes().filter(|b| !b.is_ascii_whitespace()));
+        }
+    }
+    records
+}
+
+// add records in the order first seen, appending sequence for names seen again.
+fn push_block(records: &mut Vec<(String, Vec<u8>)>, name: &str, seq: &str) {
+    let seq = seq.bytes().filter(|b| !b.is_ascii_whitespace());
+    match records.iter_mut().find(|(n, _)| n == name) {
+        Some((_, s)) => s.extend(seq),
+        None => records.push((name.to_string(), seq.collect())),
+    }
+}
+
+// the first word of a line, and the rest of it, trimmed.
+fn first_word(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (line, ""),
+    }
+}
+
+fn parse_stockholm(contents: &str) -> Alignment {
+    let mut records = Vec::new();
+    let mut gf = Vec::new();
+    let mut gs = Vec::new();
+
+    for line in contents.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with("# STOCKHOLM") {
+            continue;
+        }
+        if line.starts_with("//") {
+            // only the first alignment in a file is read.
+            break;
+        }
+        if let Some(rest) = line.strip_prefix("#=GF") {
+            let (tag, text) = first_word(rest);
+            gf.push((tag.to_string(), text.to_string()));
+        } else if let Some(rest) = line.strip_prefix("#=GS") {
+            // names are often padded out with several spaces.
+            let (name, rest) = first_word(rest);
+            let (tag, text) = first_word(rest);
+            gs.push((name.to_string(), tag.to_string(), text.to_string()));
+        } else if line.starts_with('#') {
+            // #=GC, #=GR and comments are not kept.
+            continue;
+        } else {
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let name = parts.next().unwrap_or("");
+            let seq = parts.next().unwrap_or("");
+            push_block(&mut records, name, seq);
+        }
+    }
+
+    let mut alignment = from_records(records);
+    alignment.gf = gf;
+    alignment.gs = gs;
+    alignment
+}
+
+fn parse_clustal(contents: &str) -> Vec<(String, Vec<u8>)> {
+    let mut records = Vec::new();
+    for line in contents.lines().skip_while(|l| l.trim().is_empty()).skip(1) {
+        // conservation lines start with whitespace
+        if line.trim().is_empty() || line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let name = parts.next().unwrap_or("");
+        let seq = parts.next().unwrap_or("");
+        // an optional residue count may follow, which is dropped.
+        push_block(&mut records, name, seq);
+    }
+    records
+}
+
+// relaxed phylip, where names are separated from sequence by whitespace.
+// both sequential and interleaved layouts are read.
+fn parse_phylip(contents: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut lines = contents.lines().filter(|l| !l.trim().is_empty());
+    let header: Vec<usize> = lines
+        .next()
+        .unwrap_or("")
+        .split_whitespace()
+        .take(2)
+        .filter_map(|n| n.parse().ok())
+        .collect();
+    let (ntaxa, nchar) = match header[..] {
+        [ntaxa, nchar] if ntaxa > 0 => (ntaxa, nchar),
+        _ => return Err("Could not parse the PHYLIP header, which should be the (non-zero) number of sequences, and the number of columns.".to_string()),
+    };
+    let lines: Vec<&str> = lines.collect();
+
+    let split = |line: &str| {
+        let line = line.trim();
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or("").to_string();
+        let seq: Vec<u8> = parts
+            .next()
+            .unwrap_or("")
+            .bytes()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        (name, seq)
+    };
+    let bases = |line: &str| {
+        line.bytes()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect::<Vec<u8>>()
+    };
+
+    // try sequential first: each name line is followed by its continuation lines.
+    let mut sequential = Vec::new();
+    let mut index = 0;
+    while sequential.len() < ntaxa && index < lines.len() {
+        let (name, mut seq) = split(lines[index]);
+        index += 1;
+        while seq.len() < nchar && index < lines.len() {
+            seq.extend(bases(lines[index]));
+            index += 1;
+        }
+        sequential.push((name, seq));
+    }
+    if index == lines.len()
+        && sequential.len() == ntaxa
+        && sequential.iter().all(|(_, s)| s.len() == nchar)
+    {
+        return Ok(sequential);
+    }
+
+    // otherwise interleaved: names in the first block only.
+    let mut interleaved: Vec<(String, Vec<u8>)> =
+        lines.iter().take(ntaxa).map(|l| split(l)).collect();
+    for (i, line) in lines.iter().skip(ntaxa).enumerate() {
+        interleaved[i % ntaxa].1.extend(bases(line));
+    }
+    if interleaved.len() != ntaxa {
+        return Err(format!(
+            "The PHYLIP header says {} sequences, but there are {}.",
+            ntaxa,
+            interleaved.len()
+        ));
+    }
+    for (name, seq) in &interleaved {
+        if seq.len() != nchar {
+            return Err(format!(
+                "{} has {} columns, but the PHYLIP header says {}.",
+                name,
+                seq.len(),
+                nchar
+            ));
+        }
+    }
+    Ok(interleaved)
+}
+
+// a3m leaves out the gaps in insert (lower case) columns,
+// so pad each insert to the longest insert at that position.
+fn expand_a3m(records: Vec<(String, Vec<u8>)>) -> Vec<(String, Vec<u8>)> {
+    // split each sequence into inserts between match columns,
+    // where inserts[i] comes before match column i.
+    let split: Vec<(Vec<Vec<u8>>, Vec<u8>)> = records
+        .iter()
+        .map(|(_, seq)| {
+            let mut inserts = vec![Vec::new()];
+            let mut matches = Vec::new();
+            for &b in seq {
+                if b.is_ascii_lowercase() || b == b'.' {
+                    inserts.last_mut().unwrap().push(b);
+                } else {
+                    matches.push(b);
+                    inserts.push(Vec::new());
+                }
+            }
+            (inserts, matches)
+        })
+        .collect();
+
+    let columns = split.iter().map(|(i, _)| i.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|c| {
+            split
+                .iter()
+                .map(|(i, _)| i.get(c).map_or(0, |x| x.len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    records
+        .into_iter()
+        .zip(split)
+        .map(|((name, _), (inserts, matches))| {
+            let mut seq = Vec::new();
+            for (c, width) in widths.iter().enumerate() {
+                let insert = inserts.get(c).map_or(&[][..], |x| &x[..]);
+                seq.extend_from_slice(insert);
+                seq.extend(std::iter::repeat(b'-').take(width - insert.len()));
+                if let Some(m) = matches.get(c) {
+                    seq.push(*m);
+                }
+            }
+            (name, seq)
+        })
+        .collect()
+}
+
+/// Write an alignment as a Stockholm seed alignment, as Dfam expects.
+/// `gf` lines are written first, then any `#=GS` lines the alignment
+/// carries, the sequences, and the `#=GC RF` line from `consensus`.
+/// Columns where the consensus is a gap are insert columns, so
+/// they are `.` in the RF line and gaps in them are written as `.`.
+pub fn write_stockholm<W: Write>(
+    writer: &mut W,
+    alignment: &Alignment,
+    gf: &[(String, String)],
+    consensus: &[u8],
+) -> io::Result<()> {
+    let width = alignment
+        .matrix
+        .iter()
+        .map(|s| s.name.len())
+        .chain(std::iter::once("#=GC RF".len()))
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let insert = |i: usize| consensus.get(i).map_or(true, |b| *b == b'-');
+
+    writeln!(writer, "# STOCKHOLM 1.0")?;
+    for (tag, text) in gf {
+        writeln!(writer, "#=GF {} {}", tag, text)?;
+    }
+    for (name, tag, text) in &alignment.gs {
+        writeln!(writer, "#=GS {} {} {}", name, tag, text)?;
+    }
+    for seq in &alignment.matrix {
+        let row: String = seq
+            .sequence
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if b == b'-' && insert(i) {
+                    '.'
+                } else {
+                    b as char
+                }
+            })
+            .collect();
+        writeln!(writer, "{:width$}{}", seq.name, row, width = width)?;
+    }
+    let rf: String = consensus
+        .iter()
+        .map(|&b| if b == b'-' { '.' } else { b as char })
+        .collect();
+    writeln!(writer, "{:width$}{}", "#=GC RF", rf, width = width)?;
+    writeln!(writer, "//")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(alignment: &Alignment) -> Vec<(&str, &str)> {
+        alignment
+            .matrix
+            .iter()
+            .map(|s| (s.name.as_str(), std::str::from_utf8(&s.sequence).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn soft_masked_unaligned_fasta_is_fasta() {
+        let contents = ">a\nACGTacgtACGT\n>b\nACGTTTacgtAC\n>c\nacgtACG\n";
+        assert_eq!(detect_format(contents), Format::Fasta);
+    }
+
+    #[test]
+    fn detects_a3m_and_a2m() {
+        let a3m = ">a\nAC-GT\n>b\nACggAGTa\n>c\nA-cGGT\n";
+        assert_eq!(detect_format(a3m), Format::A3m);
+        let a2m = ">a\nAC..GT\n>b\nACggGT\n";
+        assert_eq!(detect_format(a2m), Format::A2m);
+        assert_eq!(detect_format(">a\nAC-GT\n>b\nACGGT\n"), Format::Fasta);
+    }
+
+    #[test]
+    fn expands_a3m_inserts() {
+        let alignment =
+            parse_alignment(">a\nAC-GT\n>b\nACggAGTa\n>c\nA-cGGT\n", Format::A3m).unwrap();
+        assert_eq!(
+            rows(&alignment),
+            vec![("a", "AC---GT-"), ("b", "ACggAGTa"), ("c", "A-c-GGT-")]
+        );
+    }
+
+    #[test]
+    fn stockholm_keeps_padded_gs_lines() {
+        let contents = "# STOCKHOLM 1.0\n\
+            #=GF ID    DF0000001\n\
+            #=GS seq1      AC   chr1:1-10\n\
+            seq1    ACGT\n\
+            seq2    AC-T\n\
+            seq1    GG\n\
+            seq2    G.\n\
+            //\n";
+        assert_eq!(detect_format(contents), Format::Stockholm);
+        let alignment = parse_alignment(contents, Format::Stockholm).unwrap();
+        assert_eq!(
+            alignment.gf,
+            vec![("ID".to_string(), "DF0000001".to_string())]
+        );
+        assert_eq!(
+            alignment.gs,
+            vec![(
+                "seq1".to_string(),
+                "AC".to_string(),
+                "chr1:1-10".to_string()
+            )]
+        );
+        assert_eq!(
+            rows(&alignment),
+            vec![("seq1", "ACGTGG"), ("seq2", "AC-TG-")]
+        );
+    }
+
+    #[test]
+    fn reads_sequential_and_interleaved_phylip() {
+        let sequential = "2 6\na ACG\nTTT\nb ACGTTA\n";
+        assert_eq!(detect_format(sequential), Format::Phylip);
+        let alignment = parse_alignment(sequential, Format::Phylip).unwrap();
+        assert_eq!(rows(&alignment), vec![("a", "ACGTTT"), ("b", "ACGTTA")]);
+
+        let interleaved = "2 6\na ACG\nb ACG\nTTT\nTTA\n";
+        let alignment = parse_alignment(interleaved, Format::Phylip).unwrap();
+        assert_eq!(rows(&alignment), vec![("a", "ACGTTT"), ("b", "ACGTTA")]);
+    }
+
+    #[test]
+    fn bad_phylip_is_an_error() {
+        assert!(parse_alignment("x 6\na ACGTTT\n", Format::Phylip).is_err());
+        assert!(parse_alignment("2 6\na ACGTTT\nb ACG\n", Format::Phylip).is_err());
+    }
+
+    #[test]
+    fn reads_clustal() {
+        let contents = "CLUSTAL W (1.83)\n\na  AC-G 3\nb  ACTG 4\n   ** *\n\na  TT\nb  TA\n";
+        assert_eq!(detect_format(contents), Format::Clustal);
+        let alignment = parse_alignment(contents, Format::Clustal).unwrap();
+        assert_eq!(rows(&alignment), vec![("a", "AC-GTT"), ("b", "ACTGTA")]);
+    }
+}
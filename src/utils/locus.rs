@@ -0,0 +1,273 @@
+// genomic coordinates of a sequence, parsed from its header.
+
+use regex::{Captures, Regex};
+use std::fmt;
+use std::io::{self, Write};
+
+/// The header conventions recognised by default:
+/// `contig_start-end` (as written by our BLAST extension scripts),
+/// `contig:start-end` (samtools faidx), `contig:start-end(+)` (as
+/// written by `reputils extend`), or `..` between the coordinates. All
+/// are one based and inclusive, so zero based starts (e.g. bedtools
+/// getfasta headers) need one adding first. A leading `_R_` is added by
+/// MAFFT when it reverse complements a sequence (--adjustdirection).
+pub const DEFAULT_LOCUS_REGEX: &str = r"^(?P<reversed>_R_)?(?P<contig>.+)[:_](?P<start>\d+)(?:-|\.\.)(?P<end>\d+)(?:\((?P<strand>[+-])\))?$";
+
+/// Which strand of the genome a sequence is from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strand {
+    Plus,
+    Minus,
+}
+
+impl Strand {
+    pub fn flip(self) -> Self {
+        match self {
+            Strand::Plus => Strand::Minus,
+            Strand::Minus => Strand::Plus,
+        }
+    }
+}
+
+impl fmt::Display for Strand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Strand::Plus => write!(f, "+"),
+            Strand::Minus => write!(f, "-"),
+        }
+    }
+}
+
+/// Where a sequence is in the genome. Coordinates are one based and
+/// inclusive, with `start <= end` whatever the strand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locus {
+    pub contig: String,
+    pub start: usize,
+    pub end: usize,
+    pub strand: Strand,
+}
+
+impl Locus {
+    /// Parse a header with a regex, which must have the named groups
+    /// `contig`, `start` and `end`, and may have `strand` (+ or -) and
+    /// `reversed` (matching if the sequence was reverse complemented).
+    /// Coordinates written backwards are on the minus strand.
+    pub fn parse_with(name: &str, regex: &Regex) -> Option<Self> {
+        let captures = regex.captures(name)?;
+        Self::from_captures(&captures)
+    }
+
+    fn from_captures(captures: &Captures) -> Option<Self> {
+        let contig = captures.name("contig")?.as_str().to_string();
+        let a: usize = captures.name("start")?.as_str().parse().ok()?;
+        let b: usize = captures.name("end")?.as_str().parse().ok()?;
+        // one based, so a zero is not a coordinate we understand.
+        if a == 0 || b == 0 {
+            return None;
+        }
+
+        let mut strand = match captures.name("strand").map(|s| s.as_str()) {
+            Some("-") => Strand::Minus,
+            _ if a > b => Strand::Minus,
+            _ => Strand::Plus,
+        };
+        if captures.name("reversed").is_some() {
+            strand = strand.flip();
+        }
+        Some(Locus {
+            contig,
+            start: a.min(b),
+            end: a.max(b),
+            strand,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        (self.end + 1).saturating_sub(self.start)
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Map `first..=last`, one based positions along the (ungapped)
+    /// sequence as it is in the alignment, back into the genome.
+    /// Minus strand sequences run from the high end of the locus.
+    pub fn sub_locus(&self, first: usize, last: usize) -> Self {
+        let (start, end) = match self.strand {
+            Strand::Plus => (
+                (self.start + first).saturating_sub(1),
+                (self.start + last).saturating_sub(1),
+            ),
+            Strand::Minus => (
+                (self.end + 1).saturating_sub(last),
+                (self.end + 1).saturating_sub(first),
+            ),
+        };
+        Locus {
+            contig: self.contig.clone(),
+            start,
+            end,
+            strand: self.strand,
+        }
+    }
+
+    /// The locus with `left` and `right` bases removed from either end
+    /// of the sequence as it is in the alignment.
+    pub fn trim(&self, left: usize, right: usize) -> Self {
+        self.sub_locus(left + 1, self.len().saturating_sub(right))
+    }
+
+    /// As in Dfam seed alignments, contig:start-end with start > end
+    /// for the minus strand.
+    pub fn seed_name(&self) -> String {
+        match self.strand {
+            Strand::Plus => format!("{}:{}-{}", self.contig, self.start, self.end),
+            Strand::Minus => format!("{}:{}-{}", self.contig, self.end, self.start),
+        }
+    }
+}
+
+impl fmt::Display for Locus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}-{}({})",
+            self.contig, self.start, self.end, self.strand
+        )
+    }
+}
+
+/// The regex for the default header conventions.
+pub fn default_regex() -> Regex {
+    Regex::new(DEFAULT_LOCUS_REGEX).unwrap()
+}
+
+/// Compile a user supplied locus regex, checking it has the groups
+/// `parse_with` needs.
+pub fn locus_regex(regex: &str) -> Regex {
+    let regex = Regex::new(regex).expect("[-]\tCould not compile the locus regex.");
+    let names: Vec<&str> = regex.capture_names().flatten().collect();
+    for group in ["contig", "start", "end"] {
+        if !names.contains(&group) {
+            eprintln!(
+                "[-]\tThe locus regex needs a named group (?P<{}>...). Exiting.",
+                group
+            );
+            std::process::exit(1);
+        }
+    }
+    regex
+}
+
+/// Write (name, locus) pairs as BED6. BED is zero based and half open,
+/// so the start is one less than the locus start.
+pub fn write_bed<W: Write>(writer: &mut W, loci: &[(&str, &Locus)]) -> io::Result<()> {
+    for (name, locus) in loci {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t0\t{}",
+            locus.contig,
+            locus.start.saturating_sub(1),
+            locus.end,
+            name,
+            locus.strand
+        )?;
+    }
+    Ok(())
+}
+
+// percent encode the characters GFF3 reserves in column nine.
+fn gff3_escape(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            ';' | '=' | '&' | ',' | '%' | '\t' | '\n' => format!("%{:02X}", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Write (name, locus) pairs as GFF3 `transposable_element` features of
+/// `family`, with the sequence names as the IDs.
+pub fn write_gff3<W: Write>(
+    writer: &mut W,
+    loci: &[(&str, &Locus)],
+    family: &str,
+) -> io::Result<()> {
+    writeln!(writer, "##gff-version 3")?;
+    for (name, locus) in loci {
+        writeln!(
+            writer,
+            "{}\treputils\ttransposable_element\t{}\t{}\t.\t{}\t.\tID={};Name={}",
+            gff3_escape(&locus.contig),
+            locus.start,
+            locus.end,
+            locus.strand,
+            gff3_escape(name),
+            gff3_escape(family)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str) -> Option<Locus> {
+        Locus::parse_with(name, &default_regex())
+    }
+
+    fn locus(start: usize, end: usize, strand: Strand) -> Locus {
+        Locus {
+            contig: "chr1".to_string(),
+            start,
+            end,
+            strand,
+        }
+    }
+
+    #[test]
+    fn parses_default_headers() {
+        let plus = locus(100, 200, Strand::Plus);
+        let minus = locus(100, 200, Strand::Minus);
+        assert_eq!(parse("chr1_100-200"), Some(plus.clone()));
+        assert_eq!(parse("chr1:100..200"), Some(plus.clone()));
+        assert_eq!(parse("chr1:100-200(+)"), Some(plus));
+        assert_eq!(parse("chr1:200-100"), Some(minus.clone()));
+        assert_eq!(parse("chr1:100-200(-)"), Some(minus.clone()));
+        assert_eq!(parse("_R_chr1_100-200"), Some(minus));
+        assert_eq!(parse("chr1"), None);
+        assert_eq!(parse("chr1:0-200"), None);
+    }
+
+    #[test]
+    fn custom_regex() {
+        let regex = locus_regex(r"^(?P<contig>[^|]+)\|(?P<start>\d+)\|(?P<end>\d+)$");
+        let parsed = Locus::parse_with("chr1|100|200", &regex);
+        assert_eq!(parsed, Some(locus(100, 200, Strand::Plus)));
+    }
+
+    #[test]
+    fn sub_locus_follows_the_strand() {
+        let plus = locus(100, 200, Strand::Plus);
+        assert_eq!(plus.sub_locus(1, 10), locus(100, 109, Strand::Plus));
+        assert_eq!(plus.trim(5, 10), locus(105, 190, Strand::Plus));
+        let minus = locus(100, 200, Strand::Minus);
+        assert_eq!(minus.sub_locus(1, 10), locus(191, 200, Strand::Minus));
+        assert_eq!(minus.trim(5, 10), locus(110, 195, Strand::Minus));
+    }
+
+    #[test]
+    fn bed_is_zero_based() {
+        let mut bed = Vec::new();
+        let loci = [locus(1, 200, Strand::Minus)];
+        write_bed(&mut bed, &[("copy", &loci[0])]).unwrap();
+        assert_eq!(
+            String::from_utf8(bed).unwrap(),
+            "chr1\t0\t200\tcopy\t0\t-\n"
+        );
+    }
+}
@@ -1,5 +1,10 @@
 pub mod alignment;
 pub mod blocks;
+pub mod flanks;
+pub mod formats;
+pub mod locus;
+pub mod profile;
 pub mod revcomp;
 pub mod seqcount;
+pub mod translate;
 pub mod windows;
@@ -0,0 +1,506 @@
+/// Module for the profile of an alignment: the counts of each base in
+/// each column, worked out once and used for the consensus, trimming,
+/// coverage and diversity.
+use crate::utils::alignment::Sequence;
+use crate::utils::blocks::{BlockRecord, BlockRecords};
+use std::fmt;
+use std::ops::Deref;
+
+/// Counts of each symbol in one column, ignoring case.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColumnCounts {
+    /// A, C, G and T, in that order.
+    pub bases: [usize; 4],
+    pub n: usize,
+    pub gap: usize,
+    /// the other IUPAC codes, in `AMBIGUITY_CODES` order.
+    pub ambiguous: [usize; 10],
+    /// anything else, e.g. ? or X.
+    pub other: usize,
+}
+
+/// The IUPAC codes for two or three bases.
+pub const AMBIGUITY_CODES: &[u8; 10] = b"RYSWKMBDHV";
+
+impl ColumnCounts {
+    pub fn add(&mut self, base: u8) {
+        match base {
+            b'A' | b'a' => self.bases[0] += 1,
+            b'C' | b'c' => self.bases[1] += 1,
+            b'G' | b'g' => self.bases[2] += 1,
+            b'T' | b't' => self.bases[3] += 1,
+            b'N' | b'n' => self.n += 1,
+            b'-' => self.gap += 1,
+            _ => match AMBIGUITY_CODES
+                .iter()
+                .position(|&c| c == base.to_ascii_uppercase())
+            {
+                Some(i) => self.ambiguous[i] += 1,
+                None => self.other += 1,
+            },
+        }
+    }
+
+    /// All the sequences counted, gaps included.
+    pub fn total(&self) -> usize {
+        self.occupied() + self.gap
+    }
+
+    /// The sequences which are not a gap.
+    pub fn occupied(&self) -> usize {
+        self.alleles().iter().sum::<usize>() + self.n
+    }
+
+    /// The count of the most common symbol which is not a gap, N's
+    /// and each ambiguity code included.
+    pub fn most_common(&self) -> usize {
+        self.alleles()
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(self.n)
+    }
+
+    /// The counts diversity compares: A, C, G, T, each other ambiguity
+    /// code, then anything else as one allele. Gaps and N's are missing
+    /// data.
+    pub fn alleles(&self) -> [usize; 15] {
+        let mut alleles = [0; 15];
+        alleles[..4].copy_from_slice(&self.bases);
+        alleles[4..14].copy_from_slice(&self.ambiguous);
+        alleles[14] = self.other;
+        alleles
+    }
+
+    /// The most common of A, C, G and T (the first, if tied) and its
+    /// count, if there are any.
+    pub fn majority(&self) -> Option<(u8, usize)> {
+        let (best, count) = self
+            .bases
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, c)| **c)
+            .unwrap();
+        (*count > 0).then(|| (b"ACGT"[best], *count))
+    }
+}
+
+/// The counts of each column of an alignment. Derefs to the columns,
+/// so can be sliced like the alignment.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// the number of sequences counted.
+    pub sequences: usize,
+    pub columns: Vec<ColumnCounts>,
+}
+
+impl Deref for Profile {
+    type Target = [ColumnCounts];
+
+    fn deref(&self) -> &[ColumnCounts] {
+        &self.columns
+    }
+}
+
+impl Profile {
+    /// Count every column in one pass over the sequences. The columns
+    /// are those of the first sequence.
+    pub fn new(matrix: &[Sequence]) -> Self {
+        let mut columns = vec![ColumnCounts::default(); matrix.first().map_or(0, |s| s.len())];
+        for record in matrix {
+            for (counts, &base) in columns.iter_mut().zip(&record.sequence) {
+                counts.add(base);
+            }
+        }
+        Profile {
+            sequences: matrix.len(),
+            columns,
+        }
+    }
+
+    /// Columns with fewer than `miss` gaps, and where the most common
+    /// base is in more than `iden` of the sequences.
+    pub fn find_blocks(&self, miss: f64, iden: f64) -> BlockRecords {
+        let mut blocks: BlockRecords = BlockRecords(Vec::new());
+        for (position, counts) in self.iter().enumerate() {
+            let col_len = counts.total() as f64;
+            let best = counts.most_common();
+            // percent identical, none if gaps are the most common.
+            let per_identity = if counts.gap > best {
+                0.0
+            } else {
+                best as f64 / col_len
+            };
+            let per_missing = counts.gap as f64 / col_len;
+
+            // add a block IF
+            // missing (dashes) below 0.1 AND column should be 0.9 identical.
+            if per_missing < miss && per_identity > iden {
+                blocks.add_record(BlockRecord {
+                    position,
+                    identity: per_identity,
+                    missing: per_missing,
+                })
+            }
+        }
+        blocks
+    }
+
+    /// Like `find_blocks`, but identity is among the sequences which are
+    /// not a gap, so columns only some copies reach (e.g. the 5' end of
+    /// a LINE, as most copies are truncated) are still hits. At least
+    /// `min_occupancy` of the sequences must not be a gap.
+    pub fn find_blocks_gap_aware(&self, min_occupancy: f64, iden: f64) -> BlockRecords {
+        let mut blocks: BlockRecords = BlockRecords(Vec::new());
+        for (position, counts) in self.iter().enumerate() {
+            let occupied = counts.occupied();
+            let best = counts.most_common();
+            // need two bases to agree for identity to mean anything.
+            if occupied < 2 {
+                continue;
+            }
+            let per_identity = best as f64 / occupied as f64;
+            let per_missing = 1.0 - occupied as f64 / counts.total() as f64;
+
+            if 1.0 - per_missing >= min_occupancy && per_identity > iden {
+                blocks.add_record(BlockRecord {
+                    position,
+                    identity: per_identity,
+                    missing: per_missing,
+                })
+            }
+        }
+        blocks
+    }
+
+    /// The number of sequences which are not a gap in each column.
+    pub fn coverage(&self) -> Vec<usize> {
+        self.iter().map(|c| c.occupied()).collect()
+    }
+
+    /// Nucleotide diversity in windows of `window_size` columns, every
+    /// `window_step` columns. Windows where fewer than `min_sites` sites
+    /// are compared per pair (on average) have no per site diversity.
+    pub fn div_windows(
+        &self,
+        window_size: usize,
+        window_step: usize,
+        min_sites: f32,
+    ) -> Vec<DiversityWindow> {
+        let columns = self.column_sums();
+        let pairs = (self.sequences * self.sequences.saturating_sub(1) / 2).max(1) as f32;
+
+        // the sums over columns lo..hi, kept up to date as the window
+        // slides, rather than summing each window from scratch.
+        let mut window = ColumnSums::default();
+        let (mut lo, mut hi) = (0, 0);
+        let mut start: usize = 0; // index to start the window
+        let mut end: usize = start + window_size; // index to end the window
+
+        let mut data = Vec::new();
+
+        while end <= columns.len() {
+            // no overlap with the last window.
+            if start >= hi {
+                window = ColumnSums::default();
+                lo = start;
+                hi = start;
+            }
+            for column in &columns[lo..start] {
+                window.remove(column);
+            }
+            for column in &columns[hi..end] {
+                window.add(column);
+            }
+            lo = start;
+            hi = end;
+
+            let mean_sites = window.compared as f32 / pairs;
+            let enough = mean_sites >= min_sites && window.compared > 0;
+            let site_stats = window.site_stats();
+            data.push(DiversityWindow {
+                start,
+                end,
+                pi: window.differences as f32 / pairs,
+                pi_per_site: enough.then(|| window.differences as f32 / window.compared as f32),
+                sites: mean_sites,
+                segregating_sites: site_stats.segregating_sites,
+                watterson: (enough && site_stats.sites > 0)
+                    .then(|| (site_stats.watterson / site_stats.sites as f64) as f32),
+                tajimas_d: site_stats.tajimas_d.filter(|_| enough).map(|d| d as f32),
+            });
+
+            start += window_step;
+            end += window_step;
+        }
+
+        data
+    }
+
+    // the sums for each column, from the counts of each base.
+    fn column_sums(&self) -> Vec<ColumnSums> {
+        self.iter().map(ColumnSums::from_counts).collect()
+    }
+
+    // heavily poached from https://github.com/noahaus/sliding-window-scripts/blob/13d872b379a3501ca9b506f8bcccf89a9cd81c8d/tjd/src/main.rs
+
+    /// Mean number of differences between pairs of sequences.
+    pub fn calculate_pi(&self) -> f32 {
+        // sum the pairwise distances and then divide by n(n-1)/2
+        let (differences, _, pairs) = self.pairwise_differences();
+        differences as f32 / pairs as f32
+    }
+
+    /// Differences per site compared, summed over all pairs, so pairs
+    /// which overlap more count for more, and gaps don't lower it.
+    pub fn calculate_pi_per_site(&self) -> Option<f32> {
+        let (differences, sites, _) = self.pairwise_differences();
+        (sites > 0).then(|| differences as f32 / sites as f32)
+    }
+
+    /// Differences and sites compared, summed over all pairs of
+    /// sequences, and the number of pairs. Worked out from the counts
+    /// of each base in each column, rather than comparing every pair.
+    pub fn pairwise_differences(&self) -> (i32, i32, usize) {
+        let sums = ColumnSums::total(&self.column_sums());
+        let pairs = self.sequences * self.sequences.saturating_sub(1) / 2;
+        (sums.differences as i32, sums.compared as i32, pairs)
+    }
+
+    /// Segregating sites, Watterson's theta and Tajima's D. Tajima's D
+    /// uses the pi summed over columns (differences over pairs with a
+    /// base in each), and the mean number of sequences with a base at
+    /// the segregating sites as the sample size.
+    pub fn site_stats(&self) -> SiteStats {
+        ColumnSums::total(&self.column_sums()).site_stats()
+    }
+}
+
+/// Nucleotide diversity in a window of the alignment.
+#[derive(Debug, Clone)]
+pub struct DiversityWindow {
+    pub start: usize,
+    pub end: usize,
+    /// mean number of differences between pairs of sequences.
+    pub pi: f32,
+    /// differences per site compared, if enough sites were compared.
+    pub pi_per_site: Option<f32>,
+    /// mean number of sites compared per pair of sequences.
+    pub sites: f32,
+    /// columns with more than one base, ignoring gaps and N's.
+    pub segregating_sites: usize,
+    /// Watterson's theta per site, if enough sites were compared.
+    pub watterson: Option<f32>,
+    /// Tajima's D, if enough sites were compared and any segregate.
+    pub tajimas_d: Option<f32>,
+}
+
+/// Segregating sites, and Watterson's theta and Tajima's D, allowing
+/// for gaps: each column only counts the sequences with a base in it.
+#[derive(Debug, Clone, Copy)]
+pub struct SiteStats {
+    /// columns with a base in at least two sequences.
+    pub sites: usize,
+    pub segregating_sites: usize,
+    /// summed over columns, so not yet per site.
+    pub watterson: f64,
+    pub tajimas_d: Option<f64>,
+}
+
+// the sum of 1/i for i in 1..n, and of 1/i^2.
+fn harmonic(n: usize) -> (f64, f64) {
+    (1..n).fold((0.0, 0.0), |(a1, a2), i| {
+        let i = i as f64;
+        (a1 + 1.0 / i, a2 + 1.0 / (i * i))
+    })
+}
+
+// what one column adds to the diversity of a window, from the counts
+// of each allele in it (see `ColumnCounts::alleles`). this is the same
+// as comparing every pair of sequences, skipping gaps and N's, but
+// windows just sum their columns.
+#[derive(Debug, Clone, Copy, Default)]
+struct ColumnSums {
+    /// pairs of sequences which differ, and pairs with a base in both.
+    differences: i64,
+    compared: i64,
+    /// columns with a base in at least two sequences.
+    sites: usize,
+    segregating_sites: usize,
+    /// sequences with a base, at the segregating sites.
+    samples: usize,
+    watterson: f64,
+    pi: f64,
+}
+
+impl ColumnSums {
+    fn from_counts(counts: &ColumnCounts) -> Self {
+        let mut sums = ColumnSums::default();
+        let alleles = counts.alleles();
+        let n: usize = alleles.iter().sum();
+        if n < 2 {
+            return sums;
+        }
+        let pairs = n * (n - 1) / 2;
+        let same: usize = alleles.iter().map(|c| c * c.saturating_sub(1) / 2).sum();
+        sums.differences = (pairs - same) as i64;
+        sums.compared = pairs as i64;
+        sums.sites = 1;
+        if alleles.iter().filter(|&&c| c > 0).count() > 1 {
+            sums.segregating_sites = 1;
+            sums.samples = n;
+            sums.watterson = 1.0 / harmonic(n).0;
+            sums.pi = (pairs - same) as f64 / pairs as f64;
+        }
+        sums
+    }
+
+    fn total(columns: &[ColumnSums]) -> Self {
+        let mut sums = ColumnSums::default();
+        for column in columns {
+            sums.add(column);
+        }
+        sums
+    }
+
+    fn add(&mut self, other: &ColumnSums) {
+        self.differences += other.differences;
+        self.compared += other.compared;
+        self.sites += other.sites;
+        self.segregating_sites += other.segregating_sites;
+        self.samples += other.samples;
+        self.watterson += other.watterson;
+        self.pi += other.pi;
+    }
+
+    fn remove(&mut self, other: &ColumnSums) {
+        self.differences -= other.differences;
+        self.compared -= other.compared;
+        self.sites -= other.sites;
+        self.segregating_sites -= other.segregating_sites;
+        self.samples -= other.samples;
+        self.watterson -= other.watterson;
+        self.pi -= other.pi;
+    }
+
+    fn site_stats(&self) -> SiteStats {
+        let segregating_sites = self.segregating_sites;
+        let tajimas_d = (segregating_sites > 0)
+            .then(|| {
+                let n = (self.samples as f64 / segregating_sites as f64).round() as usize;
+                let (a1, a2) = harmonic(n);
+                let nf = n as f64;
+                let b1 = (nf + 1.0) / (3.0 * (nf - 1.0));
+                let b2 = 2.0 * (nf * nf + nf + 3.0) / (9.0 * nf * (nf - 1.0));
+                let c1 = b1 - 1.0 / a1;
+                let c2 = b2 - (nf + 2.0) / (a1 * nf) + a2 / (a1 * a1);
+                let (e1, e2) = (c1 / a1, c2 / (a1 * a1 + a2));
+                let s = segregating_sites as f64;
+                let variance = e1 * s + e2 * s * (s - 1.0);
+                (variance > 0.0).then(|| (self.pi - s / a1) / variance.sqrt())
+            })
+            .flatten();
+
+        SiteStats {
+            sites: self.sites,
+            segregating_sites,
+            // clear the rounding left by sliding the window.
+            watterson: if segregating_sites > 0 {
+                self.watterson
+            } else {
+                0.0
+            },
+            tajimas_d,
+        }
+    }
+}
+
+impl fmt::Display for DiversityWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{:.3}\t{}\t{:.1}",
+            self.start,
+            self.end,
+            self.pi,
+            self.pi_per_site
+                .map_or("-".to_string(), |p| format!("{:.4}", p)),
+            self.sites
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[&str]) -> Vec<Sequence> {
+        rows.iter()
+            .enumerate()
+            .map(|(i, row)| Sequence {
+                name: i.to_string(),
+                sequence: row.as_bytes().to_vec(),
+                locus: None,
+            })
+            .collect()
+    }
+
+    // the old way: compare every pair, skipping gaps and N's.
+    fn hamming_distances(rows: &[&str]) -> (i32, i32) {
+        let (mut differences, mut compared) = (0, 0);
+        for (i, a) in rows.iter().enumerate() {
+            for b in &rows[i + 1..] {
+                for (x, y) in a.bytes().zip(b.bytes()) {
+                    let (x, y) = (x.to_ascii_uppercase(), y.to_ascii_uppercase());
+                    if x == b'-' || y == b'-' || x == b'N' || y == b'N' {
+                        continue;
+                    }
+                    compared += 1;
+                    if x != y {
+                        differences += 1;
+                    }
+                }
+            }
+        }
+        (differences, compared)
+    }
+
+    #[test]
+    fn column_sums_match_pairwise_hamming() {
+        // the fifth column mixes R and Y, the sixth R and A.
+        let rows = [
+            "ACGTRA-CNTY",
+            "ACGAYAACGTR",
+            "aCGtrT-CGNy",
+            "TCG-YRNCGTR",
+            "AC-TRAACGAY",
+        ];
+        let profile = Profile::new(&matrix(&rows));
+        let (differences, compared, pairs) = profile.pairwise_differences();
+        assert_eq!((differences, compared), hamming_distances(&rows));
+        assert_eq!(pairs, 10);
+    }
+
+    #[test]
+    fn each_ambiguity_code_is_an_allele() {
+        let profile = Profile::new(&matrix(&["AR", "AR", "NA", "NA", "N-"]));
+        assert_eq!(profile[0].most_common(), 3);
+        assert_eq!(&profile[1].alleles()[..5], &[2, 0, 0, 0, 2]);
+        assert_eq!(profile.site_stats().segregating_sites, 1);
+        // R and Y differ.
+        let profile = Profile::new(&matrix(&["R", "R", "Y", "Y"]));
+        assert_eq!(profile.pairwise_differences().0, 4);
+    }
+
+    #[test]
+    fn ambiguity_codes_are_not_identical() {
+        let rows = ["RA", "RA", "RA", "RA", "RA", "YA", "YA", "YA", "YA", "YA"];
+        let blocks = Profile::new(&matrix(&rows)).find_blocks(0.1, 0.9);
+        assert_eq!(blocks.0.len(), 1);
+        assert_eq!(blocks.0[0].position, 1);
+        let column = Profile::new(&matrix(&rows))[0];
+        assert_eq!(column.most_common(), 5);
+    }
+}
@@ -1,4 +1,7 @@
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::iter::Peekable;
+
 pub struct SequentialCount<I>
 where
     I: Iterator,
@@ -47,3 +50,15 @@ where
         }
     }
 }
+
+/// The most common item, and how many times it was seen. Ties go
+/// to the greatest item.
+pub fn most_common<T: Hash + Eq + Ord, I: IntoIterator<Item = T>>(items: I) -> Option<(T, usize)> {
+    let mut counts: HashMap<T, usize> = HashMap::new();
+    for item in items {
+        *counts.entry(item).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)))
+}
@@ -0,0 +1,71 @@
+// Translation of nucleotide sequences using the standard genetic code.
+// IUPAC ambiguity codes are expanded, and a codon only translates to
+// an amino acid if every expansion agrees, otherwise it is an X.
+
+// codons in TCAG order, e.g. TTT, TTC, TTA, TTG, TCT...
+const CODE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+// the unambiguous bases (as TCAG indices) an IUPAC code stands for.
+fn expand(base: u8) -> &'static [usize] {
+    match base.to_ascii_uppercase() {
+        b'T' | b'U' => &[0],
+        b'C' => &[1],
+        b'A' => &[2],
+        b'G' => &[3],
+        b'Y' => &[0, 1],
+        b'R' => &[2, 3],
+        b'W' => &[0, 2],
+        b'S' => &[1, 3],
+        b'K' => &[0, 3],
+        b'M' => &[1, 2],
+        b'B' => &[0, 1, 3],
+        b'D' => &[0, 2, 3],
+        b'H' => &[0, 1, 2],
+        b'V' => &[1, 2, 3],
+        // N, ? and anything else could be any base
+        _ => &[0, 1, 2, 3],
+    }
+}
+
+/// Translate a single codon.
+pub fn translate_codon(codon: &[u8]) -> u8 {
+    let mut aa = None;
+    for &a in expand(codon[0]) {
+        for &b in expand(codon[1]) {
+            for &c in expand(codon[2]) {
+                let this = CODE[a * 16 + b * 4 + c];
+                match aa {
+                    None => aa = Some(this),
+                    Some(x) if x != this => return b'X',
+                    _ => (),
+                }
+            }
+        }
+    }
+    aa.unwrap()
+}
+
+/// Translate a sequence from its first base, ignoring any trailing
+/// partial codon.
+pub fn translate(seq: &[u8]) -> Vec<u8> {
+    seq.chunks_exact(3).map(translate_codon).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_ambiguous_codons() {
+        assert_eq!(translate_codon(b"ATG"), b'M');
+        assert_eq!(translate_codon(b"atg"), b'M');
+        assert_eq!(translate_codon(b"TAA"), b'*');
+        // every expansion agrees.
+        assert_eq!(translate_codon(b"GCN"), b'A');
+        assert_eq!(translate_codon(b"TAR"), b'*');
+        assert_eq!(translate_codon(b"YTR"), b'L');
+        // TTT and TTC are F, TTA and TTG are L.
+        assert_eq!(translate_codon(b"TTN"), b'X');
+        assert_eq!(translate(b"ATGAAATAAC"), b"MK*");
+    }
+}